@@ -0,0 +1,164 @@
+use std::{
+    num::NonZeroUsize,
+    path::PathBuf,
+};
+
+use crate::{
+    CliError,
+    ProjectConfig,
+    command::Command,
+    log::Verbosity,
+};
+
+/// A command line split into the command, its positional arguments and the options
+pub struct Args
+{
+    pub command: Command,
+    pub positional: Vec<String>,
+    pub options: Options,
+}
+
+/// Options that can go anywhere on the command line, before or after the command
+pub struct Options
+{
+    /// Config file read instead of `v2df_config.json` in the command's directory
+    pub config_file: Option<PathBuf>,
+    /// Projects to process, all of them when empty
+    pub projects: Vec<ProjectSelector>,
+    pub threads: Option<NonZeroUsize>,
+    pub verbosity: Verbosity,
+    /// Checks the config and source and prints what would be written, without writing it
+    pub dry_run: bool,
+    /// Shows the command's help instead of running it
+    pub help: bool,
+}
+
+/// A project picked with `--project`, by its 1-based position in the config or by name
+/// (namespace for projects without one)
+pub enum ProjectSelector
+{
+    Index(NonZeroUsize),
+    Name(String),
+}
+
+impl Args
+{
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, CliError>
+    {
+        let mut command = None;
+        let mut positional = Vec::new();
+        let mut options = Options {
+            config_file: None,
+            projects: Vec::new(),
+            threads: None,
+            verbosity: Verbosity::Normal,
+            dry_run: false,
+            help: false,
+        };
+
+        while let Some(arg) = args.next()
+        {
+            let mut value = || args.next().ok_or_else(|| CliError::MissingOptionValue(arg.clone()));
+
+            match arg.as_str()
+            {
+                "-c" | "--config" => options.config_file = Some(PathBuf::from(value()?)),
+                "--project" =>
+                {
+                    options.projects.extend(value()?.split(',').map(ProjectSelector::parse));
+                },
+                "--threads" =>
+                {
+                    let threads = value()?;
+                    options.threads = Some(
+                        threads
+                            .parse()
+                            .map_err(|_| CliError::InvalidOptionValue(arg.clone(), threads))?,
+                    );
+                },
+                "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+                "--verbose" => options.verbosity = Verbosity::Verbose,
+                "--dry-run" => options.dry_run = true,
+                // Before the command `-h` and `--help` are the help command itself
+                "-h" | "--help" if command.is_some() => options.help = true,
+                _ if command.is_none() =>
+                {
+                    command = Some(Command::from_name(&arg).ok_or(CliError::UnknownCommand(arg))?);
+                },
+                // Negative numbers are arguments, like the coordinates given to `preview`
+                _ if arg.starts_with('-') && arg.parse::<f64>().is_err() =>
+                {
+                    return Err(CliError::UnknownOption(arg));
+                },
+                _ => positional.push(arg),
+            }
+        }
+
+        let command = command.ok_or(CliError::NoCommand)?;
+        options.check_supported_by(&command)?;
+
+        Ok(Args {
+            command,
+            positional,
+            options,
+        })
+    }
+}
+
+impl Options
+{
+    /// Rejects options the command has no use for, instead of silently ignoring them
+    fn check_supported_by(
+        &self,
+        command: &Command,
+    ) -> Result<(), CliError>
+    {
+        let options = [
+            ("--config", self.config_file.is_some()),
+            ("--project", !self.projects.is_empty()),
+            ("--threads", self.threads.is_some()),
+            ("--dry-run", self.dry_run),
+        ];
+
+        match options.into_iter().find(|(option, is_set)| *is_set && !command.supports(option))
+        {
+            Some((option, _)) => Err(CliError::UnsupportedOption(option, command.name())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ProjectSelector
+{
+    fn parse(selector: &str) -> ProjectSelector
+    {
+        match selector.parse()
+        {
+            Ok(index) => ProjectSelector::Index(index),
+            Err(_) => ProjectSelector::Name(selector.to_owned()),
+        }
+    }
+
+    /// `index` is the project's 0-based position in the config
+    pub fn matches(
+        &self,
+        index: usize,
+        project_config: &ProjectConfig,
+    ) -> bool
+    {
+        match self
+        {
+            ProjectSelector::Index(position) => position.get() == index + 1,
+            ProjectSelector::Name(name) => name == project_config.label(),
+        }
+    }
+
+    pub fn name(&self) -> String
+    {
+        match self
+        {
+            ProjectSelector::Index(position) => position.to_string(),
+            ProjectSelector::Name(name) => name.clone(),
+        }
+    }
+}
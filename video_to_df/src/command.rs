@@ -1,4 +1,5 @@
 use std::{
+    collections::HashSet,
     env,
     fmt::{
         Display,
@@ -18,7 +19,11 @@ use crate::{
     FormatResult,
     ImplError,
     Result,
+    args::Options,
+    evaluate::DensityFunction,
+    log::info,
     output,
+    verify,
 };
 
 #[derive(Debug)]
@@ -27,6 +32,9 @@ pub enum Command
     Init,
     Run,
     Test,
+    Preview,
+    Decode,
+    Verify,
     Help,
 }
 
@@ -36,6 +44,9 @@ impl Command
     const INIT: &'static str = "init";
     const RUN: &'static str = "run";
     const TEST: &'static str = "test";
+    const PREVIEW: &'static str = "preview";
+    const DECODE: &'static str = "decode";
+    const VERIFY: &'static str = "verify";
 
     pub fn name(&self) -> &'static str
     {
@@ -44,33 +55,83 @@ impl Command
             Self::Init => Self::INIT,
             Self::Run => Self::RUN,
             Self::Test => Self::TEST,
+            Self::Preview => Self::PREVIEW,
+            Self::Decode => Self::DECODE,
+            Self::Verify => Self::VERIFY,
             Self::Help => Self::HELP,
         }
     }
 
     pub fn from_name(name: &str) -> Option<Self>
     {
-        for cmd in [Self::Init, Self::Run, Self::Test, Self::Help]
-        {
-            if name == cmd.name() || name == cmd.alias_short() || name == cmd.alias_long()
-            {
-                return Some(cmd);
-            }
-        }
-        None
+        [Self::Init, Self::Run, Self::Test, Self::Preview, Self::Decode, Self::Verify, Self::Help]
+            .into_iter()
+            .find(|cmd| {
+                name == cmd.name() || cmd.alias_short() == Some(name) || name == cmd.alias_long()
+            })
     }
 
     pub fn execute(
         self,
-        mut args: impl Iterator<Item = String>,
+        positional: Vec<String>,
+        options: &Options,
     ) -> Result<()>
     {
+        if options.help
+        {
+            return self.execute_command_help();
+        }
+
         match self
         {
-            Self::Init => Self::execute_init(args.next().map(PathBuf::from)),
-            Self::Run => Self::execute_run(args.next().map(PathBuf::from)),
-            Self::Test => Self::execute_test(args.next().map(PathBuf::from)),
-            Self::Help => Self::execute_help(),
+            Self::Init => Self::execute_init(Self::optional_path(positional)?, options),
+            Self::Run => Self::execute_run(Self::optional_path(positional)?, options),
+            Self::Test => Self::execute_test(Self::optional_path(positional)?, options),
+            Self::Preview => Self::execute_preview(positional.into_iter(), options),
+            Self::Decode =>
+            {
+                Self::execute_decode(positional.into_iter().map(PathBuf::from).collect())
+            },
+            Self::Verify => Self::execute_verify(Self::optional_path(positional)?, options),
+            Self::Help => match positional.first()
+            {
+                None => Self::execute_help(),
+                Some(name) => Self::from_name(name)
+                    .ok_or_else(|| CliError::UnknownCommand(name.clone()))?
+                    .execute_command_help(),
+            },
+        }
+    }
+
+    /// Whether the option changes anything for the command, `--quiet`, `--verbose` and
+    /// `--help` work with every command
+    pub fn supports(
+        &self,
+        option: &str,
+    ) -> bool
+    {
+        match option
+        {
+            "--config" =>
+            {
+                matches!(self, Self::Init | Self::Run | Self::Test | Self::Preview | Self::Verify)
+            },
+            "--project" => matches!(self, Self::Run | Self::Test | Self::Preview | Self::Verify),
+            "--threads" => matches!(self, Self::Run | Self::Test | Self::Preview),
+            "--dry-run" => matches!(self, Self::Init | Self::Run | Self::Test),
+            _ => true,
+        }
+    }
+
+    /// The single `[path]` argument most commands take
+    fn optional_path(positional: Vec<String>) -> Result<Option<PathBuf>>
+    {
+        let mut positional = positional.into_iter();
+        let path = positional.next().map(PathBuf::from);
+        match positional.next()
+        {
+            Some(unexpected) => Err(CliError::UnexpectedArgument(unexpected).into()),
+            None => Ok(path),
         }
     }
 
@@ -95,17 +156,30 @@ impl Command
         }
     }
 
-    fn execute_init(path: Option<PathBuf>) -> Result<()>
+    fn execute_init(
+        path: Option<PathBuf>,
+        options: &Options,
+    ) -> Result<()>
     {
         let init_start = Instant::now();
 
-        let path = Self::get_path_or_curr_dir(path)?;
-        println!("Creating v2df project in directory: {}", path.display());
+        let config_path = Self::get_config_path(path, options)?;
+
+        if options.dry_run
+        {
+            info!("Dry run, would create v2df config: {}", config_path.display());
+            return Ok(());
+        }
+
+        info!("Creating v2df project with config: {}", config_path.display());
 
         let config = Config::default();
 
-        let config_path = path.join("v2df_config.json");
-        fs::create_dir_all(&path).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+        if let Some(config_dir) = config_path.parent()
+        {
+            fs::create_dir_all(config_dir)
+                .map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+        }
 
         let config_content = serde_json::to_string_pretty(&config)
             .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))?;
@@ -115,56 +189,155 @@ impl Command
 
         let init_time = init_start.elapsed().as_millis();
 
-        println!("Successfully created v2df project in {}", Self::format_duration(init_time));
+        info!("Successfully created v2df project in {}", Self::format_duration(init_time));
 
         Ok(())
     }
 
-    fn execute_run(path: Option<PathBuf>) -> Result<()>
+    fn execute_run(
+        path: Option<PathBuf>,
+        options: &Options,
+    ) -> Result<()>
     {
         let run_start = Instant::now();
 
-        let path = Self::get_path_or_curr_dir(path)?;
+        let config_path = Self::get_config_path(path, options)?;
 
-        println!("Running v2df in directory: {}", path.display());
+        info!("Running v2df with config: {}", config_path.display());
 
-        let config = Self::get_config(&path)?;
+        let config = Self::get_config(&config_path, options)?;
 
-        let frames = output::get_single_channel_frames(&config.video_file)?;
-
-        output::write_projects_from_config(frames, config)?;
+        output::write_projects_from_config(config, options.dry_run)?;
 
         let run_time = run_start.elapsed().as_millis();
 
-        println!("Successfully ran v2df project in {}", Self::format_duration(run_time));
+        info!("Successfully ran v2df project in {}", Self::format_duration(run_time));
 
         Ok(())
     }
 
-    fn execute_test(path: Option<PathBuf>) -> Result<()>
+    fn execute_test(
+        path: Option<PathBuf>,
+        options: &Options,
+    ) -> Result<()>
     {
         let test_start = Instant::now();
 
-        let path = Self::get_path_or_curr_dir(path)?;
+        let config_path = Self::get_config_path(path, options)?;
 
-        println!("Testing v2df in directory: {}", path.display());
+        info!("Testing v2df with config: {}", config_path.display());
 
-        let config = Self::get_config(&path)?;
+        let config = Self::get_config(&config_path, options)?;
 
-        let frames = output::get_single_channel_frames(&config.video_file)?;
-
-        output::test_projects_from_config(frames, config)?;
+        output::test_projects_from_config(config, options.dry_run)?;
 
         let test_time = test_start.elapsed().as_millis();
 
-        println!("Successfully ran v2df test in {}", Self::format_duration(test_time));
+        info!("Successfully ran v2df test in {}", Self::format_duration(test_time));
 
         Ok(())
     }
 
-    fn alias_short(&self) -> String
+    fn execute_preview(
+        mut args: impl Iterator<Item = String>,
+        options: &Options,
+    ) -> Result<()>
+    {
+        let preview_start = Instant::now();
+
+        let mut next_number = || args.next().and_then(|arg| arg.parse::<isize>().ok());
+        let (Some(x), Some(z), Some(width), Some(height)) =
+            (next_number(), next_number(), next_number(), next_number())
+        else
+        {
+            return Err(CliError::InvalidPreviewRegion.into());
+        };
+
+        let (Ok(width @ 1..), Ok(height @ 1..)) = (u16::try_from(width), u16::try_from(height))
+        else
+        {
+            return Err(CliError::InvalidPreviewRegion.into());
+        };
+
+        let config_path = Self::get_config_path(Self::optional_path(args.collect())?, options)?;
+
+        info!("Previewing v2df output with config: {}", config_path.display());
+
+        let config = Self::get_config(&config_path, options)?;
+
+        output::preview_projects_from_config(config, (x, z), (width, height))?;
+
+        let preview_time = preview_start.elapsed().as_millis();
+
+        info!("Successfully rendered v2df preview in {}", Self::format_duration(preview_time));
+
+        Ok(())
+    }
+
+    fn execute_decode(paths: Vec<PathBuf>) -> Result<()>
     {
-        format!("-{}", self.name().chars().next().unwrap())
+        let decode_start = Instant::now();
+
+        if paths.is_empty()
+        {
+            return Err(CliError::NoFramesToDecode.into());
+        }
+
+        for path in &paths
+        {
+            let frame = DensityFunction::read(path)?
+                .frame()
+                .ok_or_else(|| CliError::NotAFrame(path.clone()))?;
+
+            frame.save_as(&path.with_extension("png"))?;
+        }
+
+        let decode_time = decode_start.elapsed().as_millis();
+
+        info!(
+            "Successfully decoded {} frames in {}",
+            paths.len(),
+            Self::format_duration(decode_time)
+        );
+
+        Ok(())
+    }
+
+    fn execute_verify(
+        path: Option<PathBuf>,
+        options: &Options,
+    ) -> Result<()>
+    {
+        let verify_start = Instant::now();
+
+        let config_path = Self::get_config_path(path, options)?;
+
+        info!("Verifying v2df output with config: {}", config_path.display());
+
+        let config = Self::get_config(&config_path, options)?;
+
+        verify::verify_projects_from_config(config)?;
+
+        let verify_time = verify_start.elapsed().as_millis();
+
+        info!("Successfully verified v2df output in {}", Self::format_duration(verify_time));
+
+        Ok(())
+    }
+
+    /// Listed per command so none collides with an option, `verify` has none next to `--verbose`
+    fn alias_short(&self) -> Option<&'static str>
+    {
+        match self
+        {
+            Self::Init => Some("-i"),
+            Self::Run => Some("-r"),
+            Self::Test => Some("-t"),
+            Self::Preview => Some("-p"),
+            Self::Decode => Some("-d"),
+            Self::Verify => None,
+            Self::Help => Some("-h"),
+        }
     }
 
     fn alias_long(&self) -> String
@@ -172,26 +345,30 @@ impl Command
         format!("--{}", self.name())
     }
 
-    fn execute_help() -> Result<()>
+    /// The command's entry in the help message
+    fn usage(&self) -> &'static str
     {
-        println!(
-            "Usage: v2df [COMMAND]
-
-    COMMANDS:
-        init [path]    Initialize a new project in the specified directory
+        match self
+        {
+            Self::Init =>
+            {
+                "        init [path]    Initialize a new project in the specified directory
                        If no path is provided, initializes in current directory
 
                        New projects consist of a default 'v2df_config.json' file
 
-                       WARNING: overrides existing project configurations
-
-        run [path]     Execute the project in the specified directory
+                       WARNING: overrides existing project configurations"
+            },
+            Self::Run =>
+            {
+                "        run [path]     Execute the project in the specified directory
                        If no path is provided, runs project in current directory
                        If no 'v2df_config.json' file is found in the current directory, exits
-                       If no entry matching the 'video_file' field is found, exits
+                       If the source ('video_file', 'image_dir', 'image_file' or 'gif_file')
+                       can't be found, exits
 
-                       Running this project reads the 'v2df_config.json' and 'video_file'
-                       The 'video_file' is:
+                       Running this project reads the 'v2df_config.json' and the source
+                       The source is:
                        - Processed into black and white frames (single channel, mono)
                        - Adds a border
                        - Applies a gradient
@@ -203,22 +380,139 @@ impl Command
                        convert all the video's frames into data
                        that can be used as a heightmap for terrain in Minecraft
 
-                       WARNING: overrides existing project files
-
-        test [path]    Runs a single frame test for the project in the specified directory
+                       WARNING: overrides existing project files"
+            },
+            Self::Test =>
+            {
+                "        test [path]    Runs a single frame test for the project in the specified directory
                        If no path is provided, runs tests in current directory
                        If no 'v2df_config.json' file is found in the current directory, exits
-                       If no entry matching the 'video_file' field is found, exits
+                       If the source ('video_file', 'image_dir', 'image_file' or 'gif_file')
+                       can't be found, exits
 
                        The single frame test consists of:
                        - a 'frame_<n>.json'
                        - an 'all_frames.json' containing that frame's reference
                        - the frame image before processing
                        - the frame image after processing (gradient and border)
+                       - a 'preview_test_frame_<n>.png' rendered from the written
+                         'frame_<n>.json', showing exactly what the mod will sample
+
+                       WARNING: overrides existing project files"
+            },
+            Self::Preview =>
+            {
+                "        preview <x> <z> <width> <height> [path]
+                       Renders a top-down heightmap of each project's 'all_frames.json'
+                       over the world region starting at <x> <z>, from the files a
+                       previous 'run' wrote, into 'preview_<name or position>.png'
+                       Zipped packs have to be unzipped first"
+            },
+            Self::Decode =>
+            {
+                "        decode <file>...
+                       Turns frame density function files back into grayscale PNGs,
+                       written next to each file (e.g. 'frames/12.json' -> 'frames/12.png')"
+            },
+            Self::Verify =>
+            {
+                "        verify [path]  Checks the output a previous 'run' wrote for each project:
+                       - every frame in 'all_frames.json' has a readable file
+                       - each frame's data fills exactly its x_size * z_size
+                       - all frames have the same size
+                       - every frame has a tp function
+                       - the grid's IDs point into the project's frame folder
+                       Prints each problem found and exits with an error if there are any
+                       Zipped packs have to be unzipped first"
+            },
+            Self::Help =>
+            {
+                "        help [command] Show this help message, or the help of a single command"
+            },
+        }
+    }
+
+    /// Options in the help message, with the option `supports` is asked about
+    const OPTIONS: [(&'static str, &'static str); 7] = [
+        (
+            "--config",
+            "        -c, --config <file>
+                       Read this config file instead of 'v2df_config.json' in [path]",
+        ),
+        (
+            "--project",
+            "        --project <name|index>[,...]
+                       Only process these projects, by 'name' (namespace for projects
+                       without one) or 1-based position in 'projects'",
+        ),
+        (
+            "--threads",
+            "        --threads <n>  Number of threads frames are processed on
+                       Defaults to one per CPU core",
+        ),
+        ("--quiet", "        -q, --quiet    Only print errors"),
+        (
+            "--verbose",
+            "        --verbose      Also print each project's frame range, sizes and folders",
+        ),
+        (
+            "--dry-run",
+            "        --dry-run      Check the config and source and print what would be written,
+                       without writing anything",
+        ),
+        ("--help", "        -h, --help     Show the help of the command"),
+    ];
+
+    fn options_usage(&self) -> String
+    {
+        Self::OPTIONS
+            .iter()
+            .filter(|(option, _)| self.supports(option))
+            .map(|(_, usage)| *usage)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn execute_command_help(&self) -> Result<()>
+    {
+        println!(
+            "Usage: v2df {} [OPTIONS]
+
+{}
+
+    OPTIONS:
+{}",
+            self.name(),
+            self.usage(),
+            self.options_usage()
+        );
+        Ok(())
+    }
+
+    fn execute_help() -> Result<()>
+    {
+        let commands = [
+            Self::Init,
+            Self::Run,
+            Self::Test,
+            Self::Preview,
+            Self::Decode,
+            Self::Verify,
+            Self::Help,
+        ]
+        .iter()
+        .map(Self::usage)
+        .collect::<Vec<_>>()
+        .join("\n\n");
 
-                       WARNING: overrides existing project files
+        println!(
+            "Usage: v2df [OPTIONS] [COMMAND]
+
+    COMMANDS:
+{}
 
-        help           Show this help message
+    OPTIONS:
+{}
 
     ARGUMENTS:
         [path]         Optional path to target directory
@@ -229,23 +523,80 @@ impl Command
         v2df init ./my-project       # Initialize project in ./my-project
         v2df run                     # Run project in current directory
         v2df run ../other-project    # Run project in ../other-project
+        v2df run --project 2         # Run only the second project
+        v2df test --project preview  # Test only the project named preview
+        v2df run --dry-run --verbose # Print what running would write
+        v2df -c other.json test      # Run tests with the config in other.json
         v2df test ./src              # Run tests in ./src directory
-        v2df help                    # Show this help message!"
+        v2df preview 0 0 512 512     # Render the 512x512 blocks from 0 0
+        v2df decode frames/1.json    # Write frames/1.png
+        v2df verify                  # Check the output of the project in current directory
+        v2df run --help              # Show the help of the run command
+        v2df help                    # Show this help message!",
+            commands,
+            Self::Help.options_usage()
         );
         Ok(())
     }
 
-    fn get_config(path: &Path) -> Result<Config>
+    /// `--config` if given, `v2df_config.json` in the directory otherwise
+    fn get_config_path(
+        path: Option<PathBuf>,
+        options: &Options,
+    ) -> Result<PathBuf>
+    {
+        match &options.config_file
+        {
+            Some(config_file) => Ok(config_file.clone()),
+            None => Ok(Self::get_path_or_curr_dir(path)?.join("v2df_config.json")),
+        }
+    }
+
+    /// Reads the config, selecting the projects picked with `--project`
+    fn get_config(
+        config_path: &Path,
+        options: &Options,
+    ) -> Result<Config>
     {
-        let config_path = path.join("v2df_config.json");
         if !(config_path.exists() && config_path.is_file())
         {
-            return Err(CliError::ConfigNotFound(path.to_owned()).into());
+            return Err(CliError::ConfigNotFound(config_path.to_owned()).into());
+        }
+        let config_str = fs::read_to_string(config_path)
+            .map_err(|e| CliError::ConfigRead(config_path.to_owned(), format!("{:?}", e)))?;
+        let mut config: Config = serde_json::from_str(&config_str)
+            .map_err(|e| CliError::ConfigParse(config_path.to_owned(), format!("{:?}", e)))?;
+
+        let mut names = HashSet::new();
+        if let Some(name) = config
+            .projects
+            .iter()
+            .filter_map(|project| project.name.as_ref())
+            .find(|name| !names.insert(*name))
+        {
+            return Err(CliError::DuplicateProjectName(name.clone()).into());
+        }
+
+        if let Some(unknown) = options.projects.iter().find(|selector| {
+            !config.projects.iter().enumerate().any(|(i, project)| selector.matches(i, project))
+        })
+        {
+            return Err(CliError::UnknownProject(unknown.name()).into());
+        }
+
+        if !options.projects.is_empty()
+        {
+            config.selection = Some(
+                (0..config.projects.len())
+                    .filter(|&i| {
+                        options
+                            .projects
+                            .iter()
+                            .any(|selector| selector.matches(i, &config.projects[i]))
+                    })
+                    .collect(),
+            );
         }
-        let config_str = fs::read_to_string(&config_path)
-            .map_err(|e| CliError::ConfigRead(format!("{:?}", e)))?;
-        let config: Config = serde_json::from_str(&config_str)
-            .map_err(|e| CliError::ConfigParse(format!("{:?}", e)))?;
         Ok(config)
     }
 }
@@ -1,5 +1,8 @@
 use std::{
-    num::NonZeroU32,
+    num::{
+        NonZeroU16,
+        NonZeroU32,
+    },
     path::PathBuf,
 };
 
@@ -11,32 +14,281 @@ use serde::{
 #[derive(Serialize, Deserialize)]
 pub struct Config
 {
-    pub video_file: PathBuf,
+    #[serde(flatten)]
+    pub source: SourceConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_fps: Option<f64>,
     pub output_root_dir: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pack: Option<PackConfig>,
     pub projects: Vec<ProjectConfig>,
+    /// 0-based positions of the projects picked with `--project`, every project when `None`
+    #[serde(skip)]
+    pub selection: Option<Vec<usize>>,
+}
+
+/// Writes a complete datapack at `output_root_dir` instead of bare output folders
+#[derive(Serialize, Deserialize)]
+pub struct PackConfig
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pack_format: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Packs the datapack into `<output_root_dir>.zip` instead of leaving a folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zip: Option<bool>,
+}
+
+/// Where frames come from, written as exactly one of these keys in the config
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceConfig
+{
+    VideoFile(PathBuf),
+    ImageDir(ImageDirConfig),
+    ImageFile(PathBuf),
+    GifFile(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageDirConfig
+{
+    pub dir: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<SortOrder>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder
+{
+    /// Digit runs compare by value, so `frame_2.png` comes before `frame_10.png`
+    #[default]
+    Natural,
+    /// Plain lexicographic file name order
+    Name,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ProjectConfig
 {
+    /// Picks the project out with `--project`, has to be unique within the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Replaces the top-level source for this project, e.g. `{"video_file": "./intro.mp4"}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceConfig>,
+    /// Frame rate of the project's own `source`, the top-level `source_fps` only applies to the
+    /// top-level source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_fps: Option<f64>,
     pub border_width: u16,
     pub border_color: u8,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_metric: Option<DistanceMetric>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<Threshold>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gradient_scale: Option<GradientScale>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub invert_colors: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frame_start: Option<NonZeroU32>,
+    pub crop: Option<CropRect>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frame_end: Option<NonZeroU32>,
+    pub remove_letterbox: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<ScaleConfig>,
+    /// First frame to output, defaults to the first frame of the source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_start: Option<FramePosition>,
+    /// Last frame to output (included), defaults to the last frame of the source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_end: Option<FramePosition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resample: Option<Resample>,
     pub namespace: String,
     pub make_frames: bool,
     pub frame_dfs_dir: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedupe_frames: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tiles: Option<TileGrid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub world_preset: Option<WorldPresetConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playback: Option<PlaybackConfig>,
     pub make_grid: bool,
     pub grid_df_dir: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid_spacing: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid_order: Option<GridOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid_wrap: Option<NonZeroU32>,
     pub make_tp: bool,
     pub tp_height: i16,
     pub tp_dir: PathBuf,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub test_frame: Option<NonZeroU32>,
+    pub test_frame: Option<FramePosition>,
+}
+
+/// A 1-based frame number, or a timestamp like `"00:01:23.500"` or `"83.5"` (seconds)
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum FramePosition
+{
+    Frame(NonZeroU32),
+    Time(String),
+}
+
+/// Which source frames become output frames, and how many times
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Resample
+{
+    /// Every Nth source frame
+    Step(NonZeroU32),
+    /// Drops or duplicates source frames to play at this frame rate
+    Fps(f64),
+}
+
+/// Region of the source frame to keep, in source pixels
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct CropRect
+{
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Order frames are laid out in across the world
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GridOrder
+{
+    /// Outwards from (0, 0) in a square spiral
+    #[default]
+    Spiral,
+    /// Left to right, then the next row down
+    RowMajor,
+    /// Top to bottom, then the next column over
+    ColumnMajor,
+    /// Row major, but every other row runs right to left so neighbouring frames stay adjacent
+    Serpentine,
+    /// Along a Hilbert curve, keeping frames close in time close in the world
+    Hilbert,
+}
+
+/// Splits each bordered frame into `columns` x `rows` tile DFs plus a DF composing them
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct TileGrid
+{
+    pub columns: NonZeroU16,
+    pub rows: NonZeroU16,
+}
+
+/// Noise settings with the grid DF as terrain, and a world preset selecting them
+#[derive(Serialize, Deserialize)]
+pub struct WorldPresetConfig
+{
+    /// Path of the noise settings and world preset IDs in the project's namespace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Surface height where the grid DF is 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_height: Option<i32>,
+    /// Blocks the surface rises per unit of the grid DF, negative flips the terrain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height_scale: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub biome: Option<String>,
+}
+
+/// Functions playing the frames back in-game by running the tp functions in order
+#[derive(Serialize, Deserialize)]
+pub struct PlaybackConfig
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticks_per_frame: Option<NonZeroU32>,
+    /// Starts over from the first frame instead of stopping after the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<bool>,
+    /// Folder inside the pack's function folder the playback functions go in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub objective: Option<String>,
+}
+
+/// Target resolution, a missing side keeps the aspect ratio of the (cropped) source
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ScaleConfig
+{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<NonZeroU16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<NonZeroU16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<ScaleFilter>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleFilter
+{
+    Nearest,
+    #[default]
+    Bilinear,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+/// How the gradient measures the distance to the nearest pixel on the other side of the threshold
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric
+{
+    /// Square slopes with diamond corners, max(|dx|, |dz|)
+    #[default]
+    Chebyshev,
+    /// Diamond shaped slopes, |dx| + |dz|
+    Manhattan,
+    /// Exact straight-line distance, round silhouettes make round hills
+    Euclidean,
+}
+
+/// Where frames are split into the pixels above and below the threshold
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Threshold
+{
+    /// Pixels above this value are above, the default is 127
+    Fixed(u8),
+    /// Otsu's threshold over every frame in the range, found in an extra pass
+    Otsu,
+    /// Otsu's threshold for each frame on its own
+    Adaptive,
+}
+
+/// Which distance in pixels maps to the ends of the gradient
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GradientScale
+{
+    /// Each frame's own farthest distance, slopes can change steepness from frame to frame
+    #[default]
+    PerFrame,
+    /// A fixed distance in pixels, farther distances are clamped
+    Fixed(NonZeroU32),
+    /// The farthest distance across the whole frame range, found in an extra pass
+    Global,
 }
 
 impl Default for Config
@@ -44,32 +296,87 @@ impl Default for Config
     fn default() -> Self
     {
         Self {
-            video_file: PathBuf::from("input.mp4"),
+            source: SourceConfig::VideoFile(PathBuf::from("input.mp4")),
+            source_fps: None,
             output_root_dir: PathBuf::from("./output"),
+            pack: None,
             projects: vec![ProjectConfig::default()],
+            selection: None,
         }
     }
 }
 
+impl Config
+{
+    /// Projects to process along with their 0-based position in the config, which stays the
+    /// same when `--project` picks some of them
+    pub fn selected_projects(&self) -> impl Iterator<Item = (usize, &ProjectConfig)>
+    {
+        self.projects
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| self.selection.as_ref().is_none_or(|selection| selection.contains(n)))
+    }
+
+    /// The source the project reads and the frame rate it's read at
+    pub fn project_source<'a>(
+        &'a self,
+        project_config: &'a ProjectConfig,
+    ) -> (&'a SourceConfig, Option<f64>)
+    {
+        match &project_config.source
+        {
+            Some(source) => (source, project_config.source_fps),
+            None => (&self.source, project_config.source_fps.or(self.source_fps)),
+        }
+    }
+}
+
+impl ProjectConfig
+{
+    /// How the project is called in messages and file names, its namespace when it has no name
+    pub fn label(&self) -> &str
+    {
+        self.name.as_deref().unwrap_or(&self.namespace)
+    }
+}
+
 impl Default for ProjectConfig
 {
     fn default() -> Self
     {
         Self {
+            name: None,
+            source: None,
+            source_fps: None,
             border_width: 32,
             border_color: 255, // white
+            distance_metric: None,
+            threshold: None,
+            gradient_scale: None,
             invert_colors: None,
-            frame_start: Some(NonZeroU32::new(1).unwrap()),
+            crop: None,
+            remove_letterbox: None,
+            scale: None,
+            frame_start: Some(FramePosition::Frame(NonZeroU32::new(1).unwrap())),
             frame_end: None,
+            resample: None,
             namespace: String::from("namespace"),
             make_frames: true,
             frame_dfs_dir: PathBuf::from("./frames"),
+            dedupe_frames: None,
+            tiles: None,
+            world_preset: None,
+            playback: None,
             make_grid: true,
             grid_df_dir: PathBuf::from("./"),
+            grid_spacing: None,
+            grid_order: None,
+            grid_wrap: None,
             make_tp: true,
             tp_height: 220,
             tp_dir: PathBuf::from("./frame_tp"),
-            test_frame: Some(NonZeroU32::new(1).unwrap()),
+            test_frame: Some(FramePosition::Frame(NonZeroU32::new(1).unwrap())),
         }
     }
 }
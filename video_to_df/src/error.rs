@@ -17,12 +17,44 @@ pub enum CliError
 {
     NoCommand,
     UnknownCommand(String),
+    UnknownOption(String),
+    MissingOptionValue(String),
+    InvalidOptionValue(String, String),
+    UnsupportedOption(&'static str, &'static str),
+    UnexpectedArgument(String),
+    UnknownProject(String),
+    DuplicateProjectName(String),
     ConfigNotFound(PathBuf),
-    ConfigRead(String),
-    ConfigParse(String),
-    InvalidFrameRange((usize, usize), usize),
+    ConfigRead(PathBuf, String),
+    ConfigParse(PathBuf, String),
+    EmptySource,
+    InvertedFrameRange((usize, usize)),
+    InvalidFrameRange((usize, Option<usize>), usize),
     AccessCurrentDirectory,
     InvalidTestFrame(usize, usize),
+    TestFrameOutsideRange(usize, (usize, Option<usize>)),
+    BorderTooWide(u16, (u16, u16)),
+    SourceRead(PathBuf, String),
+    InvalidImagePattern(String),
+    NoImagesFound(PathBuf),
+    ImageDecode(PathBuf, String),
+    FrameSizeMismatch(PathBuf, (u32, u32), (u32, u32)),
+    SourceTooLarge(PathBuf, (u32, u32)),
+    InvalidCrop((u16, u16, u16, u16), (u16, u16)),
+    InvalidScale,
+    InvalidTargetFps(f64),
+    UnknownFrameRate,
+    InvalidTimestamp(String),
+    InvalidTiles((u16, u16), (usize, usize)),
+    SparseGrid(usize, usize),
+    PlaybackWithoutPack,
+    PlaybackWithoutTp,
+    DensityFunctionRead(String, String),
+    UnsupportedDensityFunction(String, String),
+    InvalidPreviewRegion,
+    NoFramesToDecode,
+    NotAFrame(PathBuf),
+    VerificationFailed(usize),
 }
 
 impl Error for CliError {}
@@ -39,25 +71,63 @@ impl Display for CliError
         {
             Self::NoCommand => write!(f, "Type 'help' for usage"),
             Self::UnknownCommand(cmd) => write!(f, "Unknown command '{}'", cmd),
+            Self::UnknownOption(option) =>
+            {
+                write!(f, "Unknown option '{}', type 'help' for usage", option)
+            },
+            Self::MissingOptionValue(option) => write!(f, "Option '{}' needs a value", option),
+            Self::InvalidOptionValue(option, value) =>
+            {
+                write!(f, "Invalid value '{}' for option '{}'", value, option)
+            },
+            Self::UnsupportedOption(option, command) =>
+            {
+                write!(f, "Option '{}' can't be used with '{}'", option, command)
+            },
+            Self::UnexpectedArgument(arg) =>
+            {
+                write!(f, "Unexpected argument '{}', type 'help' for usage", arg)
+            },
+            Self::UnknownProject(project) =>
+            {
+                write!(f, "No project with the name or position '{}' in 'projects'", project)
+            },
+            Self::DuplicateProjectName(name) =>
+            {
+                write!(f, "More than one project is named '{}', names have to be unique", name)
+            },
             Self::ConfigNotFound(path) =>
             {
-                write!(f, "Failed to find 'v2df_config.json' in directory: {}", path.display())
+                write!(f, "Failed to find config file: {}", path.display())
             },
-            Self::ConfigParse(serde_err) =>
+            Self::ConfigParse(path, serde_err) =>
             {
-                write!(f, "Failed to parse 'v2df_config.json'\n\n{}", serde_err)
+                write!(f, "Failed to parse config file '{}'\n\n{}", path.display(), serde_err)
             },
             Self::AccessCurrentDirectory => write!(f, "Could not access current directory"),
-            Self::ConfigRead(io_err) =>
+            Self::ConfigRead(path, io_err) =>
             {
-                write!(f, "Failed to read 'v2df_config.json'\n\n{}", io_err)
+                write!(f, "Failed to read config file '{}'\n\n{}", path.display(), io_err)
+            },
+            Self::EmptySource => write!(f, "The source has no frames"),
+            Self::InvertedFrameRange(frame_range) =>
+            {
+                write!(
+                    f,
+                    "Frame range [{}, {}] ends before it starts, 'frame_end' can't come before \
+                     'frame_start'",
+                    frame_range.0, frame_range.1
+                )
             },
             Self::InvalidFrameRange(frame_range, frame_count) =>
             {
+                let frame_end =
+                    frame_range.1.map_or("the last frame".to_owned(), |end| end.to_string());
                 write!(
                     f,
-                    "Frame range [{}, {}] is out of range of frame count {}",
-                    frame_range.0, frame_range.1, frame_count
+                    "Frame range [{}, {}] is out of range of frame count {}, the source's frames \
+                     are numbered 1 to {}",
+                    frame_range.0, frame_end, frame_count, frame_count
                 )
             },
             Self::InvalidTestFrame(test_frame, frame_count) =>
@@ -68,6 +138,150 @@ impl Display for CliError
                     test_frame, frame_count
                 )
             },
+            Self::TestFrameOutsideRange(test_frame, frame_range) =>
+            {
+                let frame_end =
+                    frame_range.1.map_or("the last frame".to_owned(), |end| end.to_string());
+                write!(
+                    f,
+                    "Test frame {} is outside the frame range [{}, {}], 'test_frame' has to be \
+                     one of the frames 'run' outputs",
+                    test_frame, frame_range.0, frame_end
+                )
+            },
+            Self::BorderTooWide(border_width, frame_dim) =>
+            {
+                write!(
+                    f,
+                    "A border of {} around {}x{} frames makes them larger than {}x{}, the \
+                     largest frame size",
+                    border_width,
+                    frame_dim.0,
+                    frame_dim.1,
+                    u16::MAX,
+                    u16::MAX
+                )
+            },
+            Self::SourceRead(path, io_err) =>
+            {
+                write!(f, "Failed to read source '{}'\n\n{}", path.display(), io_err)
+            },
+            Self::InvalidImagePattern(pattern_err) =>
+            {
+                write!(f, "Failed to parse image 'pattern'\n\n{}", pattern_err)
+            },
+            Self::NoImagesFound(path) =>
+            {
+                write!(f, "Found no images matching 'pattern' in directory: {}", path.display())
+            },
+            Self::ImageDecode(path, image_err) =>
+            {
+                write!(f, "Failed to decode image '{}'\n\n{}", path.display(), image_err)
+            },
+            Self::FrameSizeMismatch(path, expected, found) =>
+            {
+                write!(
+                    f,
+                    "Image '{}' is {}x{}, but the first frame is {}x{}",
+                    path.display(),
+                    found.0,
+                    found.1,
+                    expected.0,
+                    expected.1
+                )
+            },
+            Self::SourceTooLarge(path, frame_dim) =>
+            {
+                write!(
+                    f,
+                    "Source '{}' is {}x{}, frames can be at most {}x{}",
+                    path.display(),
+                    frame_dim.0,
+                    frame_dim.1,
+                    u16::MAX,
+                    u16::MAX
+                )
+            },
+            Self::InvalidCrop(crop, frame_dim) =>
+            {
+                write!(
+                    f,
+                    "Crop {}x{} at ({}, {}) doesn't fit inside the {}x{} source frame",
+                    crop.2, crop.3, crop.0, crop.1, frame_dim.0, frame_dim.1
+                )
+            },
+            Self::InvalidScale => write!(f, "'scale' needs at least one of 'width' or 'height'"),
+            Self::InvalidTargetFps(fps) =>
+            {
+                write!(f, "Resample fps {} must be greater than 0", fps)
+            },
+            Self::UnknownFrameRate =>
+            {
+                write!(
+                    f,
+                    "Source has no known frame rate to resample or resolve timestamps with, set \
+                     'source_fps'"
+                )
+            },
+            Self::InvalidTimestamp(timestamp) =>
+            {
+                write!(
+                    f,
+                    "Failed to parse timestamp '{}', expected e.g. '00:01:23.500' or '83.5'",
+                    timestamp
+                )
+            },
+            Self::InvalidTiles(tiles, frame_dim) =>
+            {
+                write!(
+                    f,
+                    "Can't split the {}x{} bordered frames into {}x{} tiles, the columns have to \
+                     divide the width and the rows the height evenly",
+                    frame_dim.0, frame_dim.1, tiles.0, tiles.1
+                )
+            },
+            Self::SparseGrid(cell_count, spiral_len) =>
+            {
+                write!(
+                    f,
+                    "The grid would spiral over {} cells to place {} frames, set a 'grid_wrap' \
+                     closer to a square or use another 'grid_order'",
+                    spiral_len, cell_count
+                )
+            },
+            Self::PlaybackWithoutPack =>
+            {
+                write!(f, "'playback' needs 'pack' to know the IDs of the functions it calls")
+            },
+            Self::PlaybackWithoutTp =>
+            {
+                write!(f, "'playback' plays the tp functions back, so it needs 'make_tp'")
+            },
+            Self::DensityFunctionRead(id, reason) =>
+            {
+                write!(f, "Failed to read density function '{}'\n\n{}", id, reason)
+            },
+            Self::UnsupportedDensityFunction(id, df_type) =>
+            {
+                write!(f, "Density function '{}' uses '{}', which can't be previewed", id, df_type)
+            },
+            Self::InvalidPreviewRegion =>
+            {
+                write!(f, "'preview' needs a region as <x> <z> <width> <height>")
+            },
+            Self::NoFramesToDecode => write!(f, "'decode' needs at least one frame JSON file"),
+            Self::NotAFrame(path) =>
+            {
+                write!(
+                    f,
+                    "'{}' isn't a frame density function (tiled frames are decoded per tile)",
+                    path.display()
+                )
+            },
+            Self::VerificationFailed(problem_count) =>
+            {
+                write!(f, "Verification found {} problems in the output", problem_count)
+            },
         }?;
         writeln!(f)
     }
@@ -76,14 +290,15 @@ impl Display for CliError
 #[derive(Debug, Clone)]
 pub enum ImplError
 {
-    AccessProjectConfig,
     ImageCreation,
     ImageSaving,
     JsonPrettifier(String),
     FileCompression(String),
     FileWrite(String),
+    FileReadBack(String),
     FetchVideoStream,
     CreateDirectory(String),
+    ThreadPool(String),
     FFmpeg(FFmpegError),
 }
 
@@ -99,10 +314,6 @@ impl Display for ImplError
         write!(f, "v2df: ")?;
         match self
         {
-            Self::AccessProjectConfig =>
-            {
-                write!(f, "Somehow failed to acess the project config from config")
-            },
             Self::ImageCreation => write!(f, "Somehow failed to create image"),
             Self::ImageSaving => write!(f, "Somehow failed to save image"),
             Self::JsonPrettifier(e) =>
@@ -116,10 +327,15 @@ impl Display for ImplError
             Self::FetchVideoStream => write!(f, "Somehow failed to fetch video stream"),
             Self::FFmpeg(e) => write!(f, "Somehow failed during video processing\n\n{}", e),
             Self::FileWrite(e) => write!(f, "Somehow failed to write file during output\n\n{}", e),
+            Self::FileReadBack(e) =>
+            {
+                write!(f, "Somehow failed to read back a file written during output\n\n{}", e)
+            },
             Self::CreateDirectory(e) =>
             {
                 write!(f, "Somehow failed to create directory during output\n\n{}", e)
             },
+            Self::ThreadPool(e) => write!(f, "Somehow failed to set up the thread pool\n\n{}", e),
         }?;
         writeln!(f)
     }
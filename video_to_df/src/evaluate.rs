@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use base64::{
+    Engine as _,
+    engine::general_purpose,
+};
+use flate2::read::ZlibDecoder;
+use rayon::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    CliError,
+    MonoFrame,
+    PackConfig,
+    layout,
+    pack,
+};
+
+/// The density function types v2df writes, parsed back out of their JSON
+pub enum DensityFunction
+{
+    Constant(f64),
+    /// Another DF file, loaded the first time it gets sampled
+    Reference(String),
+    /// `flat_cache` and `cache_2d` only change how often the mod samples their argument
+    Cache(Box<DensityFunction>),
+    Tessellation
+    {
+        x_size: usize,
+        z_size: usize,
+        data: Vec<u8>,
+    },
+    Grid
+    {
+        spacing: usize,
+        x_size: usize,
+        z_size: usize,
+        out_of_bounds_argument: Box<DensityFunction>,
+        grid_cell_args: Vec<DensityFunction>,
+    },
+}
+
+impl DensityFunction
+{
+    /// Reads a DF file straight from its path, references in it are left unresolved
+    pub fn read(df_path: &Path) -> Result<DensityFunction, CliError>
+    {
+        let id = df_path.display().to_string();
+        let df_string = fs::read_to_string(df_path)
+            .map_err(|e| CliError::DensityFunctionRead(id.clone(), format!("{:?}", e)))?;
+        let df_json: Value = serde_json::from_str(&df_string)
+            .map_err(|e| CliError::DensityFunctionRead(id.clone(), format!("{:?}", e)))?;
+        parse_density_function(&df_json, &id)
+    }
+
+    /// The image inside a frame (or tile) DF, `None` for anything else
+    pub fn frame(&self) -> Option<MonoFrame>
+    {
+        match self
+        {
+            DensityFunction::Cache(argument) => argument.frame(),
+            DensityFunction::Tessellation {
+                x_size,
+                z_size,
+                data,
+            } => Some(MonoFrame::new(data.clone(), *x_size as u16, *z_size as u16)),
+            _ => None,
+        }
+    }
+}
+
+/// Samples DF files written to `root_dir`, the way the mod would at y = 0
+pub struct DensityFunctionLoader<'a>
+{
+    root_dir: &'a Path,
+    pack: Option<&'a PackConfig>,
+    loaded: Mutex<HashMap<String, Arc<DensityFunction>>>,
+}
+
+impl<'a> DensityFunctionLoader<'a>
+{
+    pub fn new(
+        root_dir: &'a Path,
+        pack: Option<&'a PackConfig>,
+    ) -> DensityFunctionLoader<'a>
+    {
+        DensityFunctionLoader {
+            root_dir,
+            pack,
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads the DF file for an ID like `namespace:frames/1`
+    pub fn load(
+        &self,
+        id: &str,
+    ) -> Result<Arc<DensityFunction>, CliError>
+    {
+        if let Some(density_function) = self.loaded.lock().unwrap().get(id)
+        {
+            return Ok(density_function.clone());
+        }
+
+        let density_function = Arc::new(DensityFunction::read(&self.df_path(id))?);
+
+        self.loaded.lock().unwrap().insert(id.to_owned(), density_function.clone());
+        Ok(density_function)
+    }
+
+    /// Where the DF file for an ID like `namespace:frames/1` lives
+    pub fn df_path(
+        &self,
+        id: &str,
+    ) -> PathBuf
+    {
+        let (namespace, path) = id.split_once(':').unwrap_or(("minecraft", id));
+        pack::density_function_dir(self.root_dir, self.pack, namespace)
+            .join(format!("{}.json", path))
+    }
+
+    pub fn sample(
+        &self,
+        density_function: &DensityFunction,
+        x: isize,
+        z: isize,
+    ) -> Result<f64, CliError>
+    {
+        match density_function
+        {
+            DensityFunction::Constant(value) => Ok(*value),
+            DensityFunction::Reference(id) =>
+            {
+                let referenced = self.load(id)?;
+                self.sample(&referenced, x, z)
+            },
+            DensityFunction::Cache(argument) => self.sample(argument, x, z),
+            DensityFunction::Tessellation {
+                x_size,
+                z_size,
+                data,
+            } =>
+            {
+                let (x, z) = (x.rem_euclid(*x_size as isize), z.rem_euclid(*z_size as isize));
+                Ok(data[z as usize * x_size + x as usize] as f64)
+            },
+            // Cells are sampled relative to their own corner, the gaps are out of bounds. The
+            // grids v2df writes only hold frames or tiles, tessellations wrapping at a size that
+            // divides the pitch, so they come out the same if moredfs passes on world
+            // coordinates instead. A grid nested in a cell wouldn't
+            DensityFunction::Grid {
+                spacing,
+                x_size,
+                z_size,
+                out_of_bounds_argument,
+                grid_cell_args,
+            } =>
+            {
+                let (pitch_x, pitch_z) =
+                    ((x_size * (spacing + 1)) as isize, (z_size * (spacing + 1)) as isize);
+                let (cell_x, cell_z) = (x.div_euclid(pitch_x), z.div_euclid(pitch_z));
+                let (local_x, local_z) = (x.rem_euclid(pitch_x), z.rem_euclid(pitch_z));
+
+                let in_gap = local_x >= *x_size as isize || local_z >= *z_size as isize;
+                match grid_cell_args.get(layout::spiral_coords_to_index(cell_x, cell_z))
+                {
+                    Some(cell_arg) if !in_gap => self.sample(cell_arg, local_x, local_z),
+                    _ => self.sample(out_of_bounds_argument, local_x, local_z),
+                }
+            },
+        }
+    }
+
+    /// Top-down heightmap of the region starting at (`x`, `z`), values clamped to 0-255
+    pub fn render(
+        &self,
+        density_function: &DensityFunction,
+        (x, z): (isize, isize),
+        (width, height): (u16, u16),
+    ) -> Result<MonoFrame, CliError>
+    {
+        let rows = (0..height as isize)
+            .into_par_iter()
+            .map(|row| {
+                (0..width as isize)
+                    .map(|column| {
+                        let value = self.sample(density_function, x + column, z + row)?;
+                        Ok(value.round().clamp(0.0, 255.0) as u8)
+                    })
+                    .collect::<Result<Vec<u8>, CliError>>()
+            })
+            .collect::<Result<Vec<Vec<u8>>, CliError>>()?;
+
+        Ok(MonoFrame::new(rows.concat(), width, height))
+    }
+}
+
+fn parse_density_function(
+    df_json: &Value,
+    id: &str,
+) -> Result<DensityFunction, CliError>
+{
+    let invalid = || CliError::DensityFunctionRead(id.to_owned(), df_json.to_string());
+
+    if let Some(value) = df_json.as_f64()
+    {
+        return Ok(DensityFunction::Constant(value));
+    }
+    if let Some(reference) = df_json.as_str()
+    {
+        return Ok(DensityFunction::Reference(reference.to_owned()));
+    }
+
+    let field = |name: &str| df_json.get(name).ok_or_else(invalid);
+    // Frames are at most u16::MAX blocks across, anything bigger can't come from v2df
+    let u16_field = |name: &str| {
+        let value = field(name)?.as_u64().ok_or_else(invalid)?;
+        u16::try_from(value).map(|value| value as usize).map_err(|_| {
+            CliError::DensityFunctionRead(
+                id.to_owned(),
+                format!("'{}' is {}, it can be at most {}", name, value, u16::MAX),
+            )
+        })
+    };
+    let size_field = |name: &str| match u16_field(name)?
+    {
+        0 => Err(CliError::DensityFunctionRead(id.to_owned(), format!("'{}' is 0", name))),
+        size => Ok(size),
+    };
+    let df_type = field("type")?.as_str().ok_or_else(invalid)?;
+
+    match df_type.strip_prefix("minecraft:").unwrap_or(df_type)
+    {
+        "flat_cache" | "cache_2d" =>
+        {
+            Ok(DensityFunction::Cache(Box::new(parse_density_function(field("argument")?, id)?)))
+        },
+        "moredfs:single_channel_image_tessellation" =>
+        {
+            let (x_size, z_size) = (size_field("x_size")?, size_field("z_size")?);
+
+            let deflated = general_purpose::STANDARD
+                .decode(field("deflated_frame_data")?.as_str().ok_or_else(invalid)?)
+                .map_err(|e| CliError::DensityFunctionRead(id.to_owned(), format!("{:?}", e)))?;
+
+            let mut data = Vec::with_capacity(x_size * z_size);
+            ZlibDecoder::new(&deflated[..])
+                .read_to_end(&mut data)
+                .map_err(|e| CliError::DensityFunctionRead(id.to_owned(), format!("{:?}", e)))?;
+
+            if data.len() != x_size * z_size
+            {
+                return Err(CliError::DensityFunctionRead(
+                    id.to_owned(),
+                    format!(
+                        "Frame data holds {} bytes, a {}x{} frame needs {}",
+                        data.len(),
+                        x_size,
+                        z_size,
+                        x_size * z_size
+                    ),
+                ));
+            }
+
+            Ok(DensityFunction::Tessellation {
+                x_size,
+                z_size,
+                data,
+            })
+        },
+        "moredfs:gapped_grid_square_spiral" => Ok(DensityFunction::Grid {
+            spacing: u16_field("spacing")?,
+            x_size: size_field("x_size")?,
+            z_size: size_field("z_size")?,
+            out_of_bounds_argument: Box::new(parse_density_function(
+                field("out_of_bounds_argument")?,
+                id,
+            )?),
+            grid_cell_args: field("grid_cell_args")?
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|cell_arg| parse_density_function(cell_arg, id))
+                .collect::<Result<_, _>>()?,
+        }),
+        _ => Err(CliError::UnsupportedDensityFunction(id.to_owned(), df_type.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde_json::json;
+
+    use super::*;
+
+    fn grid_json(
+        spacing: u64,
+        x_size: u64,
+        z_size: u64,
+    ) -> Value
+    {
+        json!(
+            {
+                "type": "moredfs:gapped_grid_square_spiral",
+                "spacing": spacing,
+                "x_size": x_size,
+                "z_size": z_size,
+                "out_of_bounds_argument": 256,
+                "grid_cell_args": ["v2df:frames/1"]
+            }
+        )
+    }
+
+    #[test]
+    fn sizes_outside_u16_are_rejected()
+    {
+        for (x_size, z_size) in [(0, 9), (16, 0), (u16::MAX as u64 + 1, 9), (16, u64::MAX)]
+        {
+            let result = parse_density_function(&grid_json(1, x_size, z_size), "v2df:all_frames");
+            assert!(
+                matches!(result, Err(CliError::DensityFunctionRead(..))),
+                "{}x{} was accepted",
+                x_size,
+                z_size
+            );
+        }
+    }
+
+    #[test]
+    fn grid_without_gaps_is_read()
+    {
+        let result = parse_density_function(&grid_json(0, u16::MAX as u64, 1), "v2df:all_frames");
+        assert!(matches!(
+            result,
+            Ok(DensityFunction::Grid {
+                spacing: 0,
+                x_size: 65535,
+                z_size: 1,
+                ..
+            })
+        ));
+    }
+}
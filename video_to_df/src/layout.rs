@@ -0,0 +1,457 @@
+use std::num::NonZeroU32;
+
+use serde_json::Value;
+
+use crate::GridOrder;
+
+/// Frames of gap between neighbouring frames in the grid
+const DEFAULT_GRID_SPACING: u16 = 1;
+
+/// Where the grid DF puts each frame, read by both the grid JSON and the tp functions so their
+/// coordinates can't drift apart
+///
+/// The grid DF always fills a square spiral, other orders list their cells at the spiral
+/// positions they land on and leave the rest of the spiral out of bounds
+pub struct GridLayout
+{
+    pub frame_dim: (usize, usize),
+    /// Gap between neighbouring frames, in frames
+    pub spacing: usize,
+    order: GridOrder,
+    /// Cells across (row major, serpentine), cells down (column major) or the curve's side length
+    /// (Hilbert), unused by the spiral
+    line_length: usize,
+    /// Moves rectangular orders so they're centered on (0, 0) like the spiral, keeping the
+    /// spiral covering them small
+    offset: (isize, isize),
+}
+
+impl GridLayout
+{
+    /// `wrap` is the number of cells before rectangular orders start the next row (or column),
+    /// squares are used when it isn't set
+    pub fn new(
+        frame_dim: (usize, usize),
+        spacing: Option<u16>,
+        order: Option<GridOrder>,
+        wrap: Option<NonZeroU32>,
+        cell_count: usize,
+    ) -> GridLayout
+    {
+        let order = order.unwrap_or_default();
+        let square_side = (cell_count as f64).sqrt().ceil().max(1.0) as usize;
+
+        let line_length = match order
+        {
+            GridOrder::Spiral => 1,
+            GridOrder::RowMajor | GridOrder::ColumnMajor | GridOrder::Serpentine =>
+            {
+                wrap.map_or(square_side, |wrap| wrap.get() as usize)
+            },
+            GridOrder::Hilbert => square_side.next_power_of_two(),
+        };
+        let line_count = cell_count.div_ceil(line_length).max(1);
+
+        let offset = match order
+        {
+            GridOrder::Spiral => (0, 0),
+            GridOrder::RowMajor | GridOrder::Serpentine =>
+            {
+                (-(line_length as isize / 2), -(line_count as isize / 2))
+            },
+            GridOrder::ColumnMajor => (-(line_count as isize / 2), -(line_length as isize / 2)),
+            GridOrder::Hilbert => (-(line_length as isize / 2), -(line_length as isize / 2)),
+        };
+
+        GridLayout {
+            frame_dim,
+            spacing: spacing.unwrap_or(DEFAULT_GRID_SPACING) as usize,
+            order,
+            line_length,
+            offset,
+        }
+    }
+
+    /// Cell coordinates of the cell, in the same units as the spiral's
+    pub fn cell_coords(
+        &self,
+        cell: usize,
+    ) -> (isize, isize)
+    {
+        let (line, position) = (cell / self.line_length, cell % self.line_length);
+
+        let (x, z) = match self.order
+        {
+            GridOrder::Spiral => return index_to_spiral_coords(cell),
+            GridOrder::RowMajor => (position, line),
+            GridOrder::ColumnMajor => (line, position),
+            GridOrder::Serpentine if line % 2 == 0 => (position, line),
+            GridOrder::Serpentine => (self.line_length - 1 - position, line),
+            GridOrder::Hilbert => hilbert_coords(self.line_length, cell),
+        };
+        (x as isize + self.offset.0, z as isize + self.offset.1)
+    }
+
+    /// Distance between the same corner of neighbouring cells, a frame plus the gap after it
+    pub fn cell_pitch(&self) -> (usize, usize)
+    {
+        (self.frame_dim.0 * (self.spacing + 1), self.frame_dim.1 * (self.spacing + 1))
+    }
+
+    /// Block coordinates of the cell's lowest x and z corner
+    pub fn cell_origin(
+        &self,
+        cell: usize,
+    ) -> (isize, isize)
+    {
+        let (x, z) = self.cell_coords(cell);
+        let (pitch_x, pitch_z) = self.cell_pitch();
+        (x * pitch_x as isize, z * pitch_z as isize)
+    }
+
+    /// Block in the middle of the frame shown in the cell
+    pub fn cell_center(
+        &self,
+        cell: usize,
+    ) -> (isize, isize)
+    {
+        let (origin_x, origin_z) = self.cell_origin(cell);
+        (origin_x + self.frame_dim.0 as isize / 2, origin_z + self.frame_dim.1 as isize / 2)
+    }
+
+    /// Spiral coordinates the cell covers, one per tile in row major order when frames are split
+    /// into `tiles` (columns, rows)
+    ///
+    /// Tiled grids step by a tile without spacing, the gaps between frames are whole tiles of
+    /// out of bounds padding so the tiles land on the blocks the untiled frame would cover
+    pub fn spiral_cells(
+        &self,
+        cell: usize,
+        tiles: Option<(usize, usize)>,
+    ) -> Vec<(isize, isize)>
+    {
+        let (x, z) = self.cell_coords(cell);
+        let Some((columns, rows)) = tiles
+        else
+        {
+            return vec![(x, z)];
+        };
+
+        let (pitch_x, pitch_z) =
+            ((columns * (self.spacing + 1)) as isize, (rows * (self.spacing + 1)) as isize);
+        (0..rows as isize)
+            .flat_map(|row| {
+                (0..columns as isize).map(move |column| (x * pitch_x + column, z * pitch_z + row))
+            })
+            .collect()
+    }
+
+    /// Spiral positions per cell, gaps included, once the cells are packed as tightly as a
+    /// square allows
+    pub fn spiral_cells_per_frame(
+        &self,
+        tiles: Option<(usize, usize)>,
+    ) -> usize
+    {
+        tiles.map_or(1, |(columns, rows)| columns * rows * (self.spacing + 1).pow(2))
+    }
+
+    /// Number of `grid_cell_args` the spiral needs to reach all the cells, everything the cells
+    /// don't land on is out of bounds padding
+    pub fn spiral_len(
+        &self,
+        cell_count: usize,
+        tiles: Option<(usize, usize)>,
+    ) -> usize
+    {
+        (0..cell_count)
+            .flat_map(|cell| self.spiral_cells(cell, tiles))
+            .map(|(x, z)| spiral_coords_to_index(x, z) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// `grid_cell_args` for the spiral grid DF, with the arguments for every cell (one per tile
+    /// when tiled, see `spiral_cells`) at their spiral indices
+    pub fn grid_cell_args(
+        &self,
+        cell_args: impl Iterator<Item = (usize, Vec<Value>)>,
+        tiles: Option<(usize, usize)>,
+        out_of_bounds_argument: Value,
+    ) -> Vec<Value>
+    {
+        let mut grid_cell_args = Vec::new();
+        for (cell, args) in cell_args
+        {
+            for ((x, z), arg) in self.spiral_cells(cell, tiles).into_iter().zip(args)
+            {
+                let spiral_index = spiral_coords_to_index(x, z);
+                if spiral_index >= grid_cell_args.len()
+                {
+                    grid_cell_args.resize(spiral_index + 1, out_of_bounds_argument.clone());
+                }
+                grid_cell_args[spiral_index] = arg;
+            }
+        }
+        grid_cell_args
+    }
+}
+
+/// Cell coordinates of the n-th cell in the square spiral the grid DF fills outwards from (0, 0)
+pub fn index_to_spiral_coords(n: usize) -> (isize, isize)
+{
+    if n == 0
+    {
+        return (0, 0);
+    }
+
+    // Find which ring/layer we're in
+    let layer = ((((n as f64).sqrt() - 1.0) / 2.0).floor() as isize) + 1;
+
+    // Find the starting index of this layer
+    let layer_start = (2 * layer - 1).pow(2);
+
+    // Position within the layer
+    let pos_in_layer = n as isize - layer_start;
+
+    // Side length of current layer
+    let side_length = 2 * layer;
+
+    // Determine which side of the square we're on and calculate coordinates
+    if pos_in_layer < side_length
+    {
+        // Right side, moving up
+        (layer, -layer + 1 + pos_in_layer)
+    }
+    else if pos_in_layer < 2 * side_length
+    {
+        // Top side, moving left
+        (layer - 1 - (pos_in_layer - side_length), layer)
+    }
+    else if pos_in_layer < 3 * side_length
+    {
+        // Left side, moving down
+        (-layer, layer - 1 - (pos_in_layer - 2 * side_length))
+    }
+    else
+    {
+        // Bottom side, moving right
+        (-layer + 1 + (pos_in_layer - 3 * side_length), -layer)
+    }
+}
+
+/// Index of the cell at the coordinates in the square spiral, the inverse of
+/// `index_to_spiral_coords`
+pub fn spiral_coords_to_index(
+    x: isize,
+    z: isize,
+) -> usize
+{
+    let layer = x.abs().max(z.abs());
+    if layer == 0
+    {
+        return 0;
+    }
+
+    let layer_start = (2 * layer - 1).pow(2);
+    let side_length = 2 * layer;
+
+    let pos_in_layer = if x == layer && z > -layer
+    {
+        // Right side, moving up
+        z + layer - 1
+    }
+    else if z == layer
+    {
+        // Top side, moving left
+        side_length + (layer - 1 - x)
+    }
+    else if x == -layer
+    {
+        // Left side, moving down
+        2 * side_length + (layer - 1 - z)
+    }
+    else
+    {
+        // Bottom side, moving right
+        3 * side_length + (x + layer - 1)
+    };
+
+    (layer_start + pos_in_layer) as usize
+}
+
+/// Coordinates of the d-th cell along a Hilbert curve filling a `side` x `side` square, `side`
+/// being a power of 2
+fn hilbert_coords(
+    side: usize,
+    d: usize,
+) -> (usize, usize)
+{
+    let (mut x, mut z) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < side
+    {
+        let rx = 1 & (t / 2);
+        let rz = 1 & (t ^ rx);
+
+        // Rotate the quadrant so the sub-curves join up
+        if rz == 0
+        {
+            if rx == 1
+            {
+                x = s - 1 - x;
+                z = s - 1 - z;
+            }
+            (x, z) = (z, x);
+        }
+
+        x += s * rx;
+        z += s * rz;
+        t /= 4;
+        s *= 2;
+    }
+    (x, z)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const ORDERS: [GridOrder; 5] = [
+        GridOrder::Spiral,
+        GridOrder::RowMajor,
+        GridOrder::ColumnMajor,
+        GridOrder::Serpentine,
+        GridOrder::Hilbert,
+    ];
+
+    #[test]
+    fn spiral_index_round_trips()
+    {
+        for n in 0..10_000
+        {
+            let (x, z) = index_to_spiral_coords(n);
+            assert_eq!(spiral_coords_to_index(x, z), n, "cell {} at ({}, {})", n, x, z);
+        }
+    }
+
+    #[test]
+    fn cells_land_on_distinct_spiral_positions()
+    {
+        for order in ORDERS
+        {
+            for (cell_count, wrap) in [(1, None), (7, None), (50, None), (50, NonZeroU32::new(3))]
+            {
+                let layout = GridLayout::new((16, 9), None, Some(order), wrap, cell_count);
+                let mut spiral_indices = HashSet::new();
+
+                for cell in 0..cell_count
+                {
+                    let (x, z) = layout.cell_coords(cell);
+                    let spiral_index = spiral_coords_to_index(x, z);
+                    assert_eq!(index_to_spiral_coords(spiral_index), (x, z));
+                    assert!(
+                        spiral_indices.insert(spiral_index),
+                        "{:?} cell {} reused",
+                        order,
+                        cell
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_cover_the_blocks_of_their_frame()
+    {
+        let (frame_dim, tiles, tile_dim) = ((12, 9), (2, 3), (6, 3));
+        for order in ORDERS
+        {
+            for spacing in [0, 1, 3]
+            {
+                let layout = GridLayout::new(frame_dim, Some(spacing), Some(order), None, 20);
+                for cell in 0..20
+                {
+                    let (origin_x, origin_z) = layout.cell_origin(cell);
+                    for (tile, (x, z)) in
+                        layout.spiral_cells(cell, Some(tiles)).into_iter().enumerate()
+                    {
+                        let (column, row) = ((tile % tiles.0) as isize, (tile / tiles.0) as isize);
+                        assert_eq!(
+                            (x * tile_dim.0, z * tile_dim.1),
+                            (origin_x + column * tile_dim.0, origin_z + row * tile_dim.1),
+                            "{:?} spacing {} cell {} tile {}",
+                            order,
+                            spacing,
+                            cell,
+                            tile
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_len_grows_with_the_distance_from_the_center()
+    {
+        // Square orders stay within a few times the cell count, Hilbert curves round their side
+        // up to a power of two
+        for order in ORDERS
+        {
+            for cell_count in [1, 40, 1025, 6500]
+            {
+                let layout = GridLayout::new((16, 9), None, Some(order), None, cell_count);
+                let spiral_len = layout.spiral_len(cell_count, None);
+                assert!(spiral_len >= cell_count);
+                assert!(
+                    spiral_len <= 8 * cell_count.max(4),
+                    "{:?} {} cells need {}",
+                    order,
+                    cell_count,
+                    spiral_len
+                );
+            }
+        }
+
+        // A single row reaches half its length out, the spiral has to cover nearly that square
+        let layout =
+            GridLayout::new((16, 9), None, Some(GridOrder::RowMajor), NonZeroU32::new(6500), 6500);
+        assert!(layout.spiral_len(6500, None) > 42_000_000);
+    }
+
+    #[test]
+    fn cells_of_a_3x3_grid_land_on_known_blocks()
+    {
+        // 4x3 frames with a gap of one frame step 8 blocks across and 6 down
+        let spiral = GridLayout::new((4, 3), Some(1), Some(GridOrder::Spiral), None, 9);
+        assert_eq!(spiral.cell_pitch(), (8, 6));
+        let spiral_origins =
+            [(0, 0), (8, 0), (8, 6), (0, 6), (-8, 6), (-8, 0), (-8, -6), (0, -6), (8, -6)];
+        let spiral_centers =
+            [(2, 1), (10, 1), (10, 7), (2, 7), (-6, 7), (-6, 1), (-6, -5), (2, -5), (10, -5)];
+        for (cell, (origin, center)) in spiral_origins.into_iter().zip(spiral_centers).enumerate()
+        {
+            assert_eq!(spiral.cell_origin(cell), origin, "spiral cell {}", cell);
+            assert_eq!(spiral.cell_center(cell), center, "spiral cell {}", cell);
+        }
+
+        // Row major fills the square row by row, centered on the middle cell
+        let row_major = GridLayout::new((4, 3), Some(1), Some(GridOrder::RowMajor), None, 9);
+        let row_major_centers =
+            [(-6, -5), (2, -5), (10, -5), (-6, 1), (2, 1), (10, 1), (-6, 7), (2, 7), (10, 7)];
+        for (cell, center) in row_major_centers.into_iter().enumerate()
+        {
+            assert_eq!(row_major.cell_center(cell), center, "row major cell {}", cell);
+        }
+
+        // Without a gap the cells touch
+        let no_gap = GridLayout::new((4, 3), Some(0), Some(GridOrder::Spiral), None, 9);
+        assert_eq!(no_gap.cell_pitch(), (4, 3));
+        assert_eq!(no_gap.cell_origin(6), (-4, -3));
+    }
+}
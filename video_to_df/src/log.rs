@@ -0,0 +1,50 @@
+use std::sync::atomic::{
+    AtomicU8,
+    Ordering,
+};
+
+/// How much progress gets printed, errors are always printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity
+{
+    /// Only errors
+    Quiet,
+    Normal,
+    /// Also per project and per batch details
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity)
+{
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(verbosity: Verbosity) -> bool
+{
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+/// `println!` unless `--quiet` is set
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Normal)
+        {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// `println!` only when `--verbose` is set
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Verbose)
+        {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use detail;
+pub(crate) use info;
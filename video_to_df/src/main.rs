@@ -1,9 +1,21 @@
+mod args;
 mod command;
 mod config;
 mod error;
+mod evaluate;
+mod layout;
+mod log;
 mod monoframe;
 mod output;
+mod pack;
+mod playback;
+mod preset;
+mod process;
 mod sdf;
+mod source;
+mod timeline;
+mod verify;
+mod video;
 
 use std::{
     env,
@@ -14,7 +26,7 @@ use std::{
 use ffmpeg_next as ffmpeg;
 
 use crate::{
-    command::Command,
+    args::Args,
     config::*,
     error::*,
     ffmpeg::Error as FFmpegError,
@@ -31,17 +43,28 @@ fn main()
         Err(e) =>
         {
             eprint!("{}", e);
+            std::process::exit(1);
         },
     }
 }
 
 fn run() -> Result<()>
 {
-    let mut args = env::args().skip(1);
+    let Args {
+        command,
+        positional,
+        options,
+    } = Args::parse(env::args().skip(1))?;
 
-    let command_str = args.next().ok_or(CliError::NoCommand)?;
+    log::set_verbosity(options.verbosity);
 
-    let command = Command::from_name(&command_str).ok_or(CliError::UnknownCommand(command_str))?;
+    if let Some(threads) = options.threads
+    {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.get())
+            .build_global()
+            .map_err(|e| ImplError::ThreadPool(format!("{:?}", e)))?;
+    }
 
-    command.execute(args)
+    command.execute(positional, &options)
 }
@@ -3,13 +3,19 @@ use std::path::Path;
 use image::{
     ImageBuffer,
     Luma,
+    imageops::{
+        self,
+        FilterType,
+    },
 };
 
 use crate::{
     ImplError,
     Result,
+    log::info,
 };
 
+#[derive(Clone)]
 pub struct MonoFrame
 {
     pub data: Vec<u8>,
@@ -32,6 +38,27 @@ impl MonoFrame
         }
     }
 
+    /// Copies a frame out of a buffer whose rows are `stride` bytes apart (e.g. ffmpeg linesize)
+    pub fn from_strided(
+        data: &[u8],
+        stride: usize,
+        width: u16,
+        height: u16,
+    ) -> MonoFrame
+    {
+        let mut tight_data = Vec::with_capacity(width as usize * height as usize);
+
+        // Copy data row by row to drop the row padding
+        for y in 0..height as usize
+        {
+            let row_start = y * stride;
+            let row_end = row_start + width as usize;
+            tight_data.extend_from_slice(&data[row_start..row_end]);
+        }
+
+        MonoFrame::new(tight_data, width, height)
+    }
+
     pub fn solid_color(
         width: u16,
         height: u16,
@@ -45,6 +72,51 @@ impl MonoFrame
         }
     }
 
+    pub fn invert(&self) -> MonoFrame
+    {
+        MonoFrame::new(
+            self.data.iter().map(|&pixel| 255 - pixel).collect(),
+            self.width,
+            self.height,
+        )
+    }
+
+    pub fn crop(
+        &self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+    ) -> MonoFrame
+    {
+        let mut cropped = Vec::with_capacity(width as usize * height as usize);
+
+        for row in y as usize..(y as usize + height as usize)
+        {
+            let row_start = row * self.width as usize + x as usize;
+            let row_end = row_start + width as usize;
+            cropped.extend_from_slice(&self.data[row_start..row_end]);
+        }
+
+        MonoFrame::new(cropped, width, height)
+    }
+
+    pub fn resize(
+        &self,
+        width: u16,
+        height: u16,
+        filter: FilterType,
+    ) -> MonoFrame
+    {
+        let img: ImageBuffer<Luma<u8>, &[u8]> =
+            ImageBuffer::from_raw(self.width as u32, self.height as u32, &self.data[..])
+                .expect("MonoFrame data should always match its size");
+
+        let resized = imageops::resize(&img, width as u32, height as u32, filter);
+
+        MonoFrame::new(resized.into_raw(), width, height)
+    }
+
     pub fn add_border(
         &self,
         border_width: u16,
@@ -62,7 +134,7 @@ impl MonoFrame
             let src_start = y as usize * self.width as usize;
             let src_end = src_start + self.width as usize;
             let dst_start =
-                ((y as usize + border_width as usize) * new_width as usize) + border_width as usize;
+                ((y as usize + border_width as usize) * new_width) + border_width as usize;
             let dst_end = dst_start + self.width as usize;
 
             with_border.data[dst_start..dst_end].copy_from_slice(&self.data[src_start..src_end]);
@@ -75,23 +147,41 @@ impl MonoFrame
         filename: &Path,
     ) -> Result<()>
     {
-        // Create image buffer from monochromatic data
-        let mut img_data = Vec::with_capacity(self.width as usize * self.height as usize);
-
-        // Copy data row by row to handle stride
-        for y in 0..self.height
-        {
-            let row_start = y as usize * self.width as usize;
-            let row_end = row_start as usize + self.width as usize;
-            img_data.extend_from_slice(&self.data[row_start..row_end]);
-        }
-
+        // Create image buffer from monochromatic data, rows are always tightly packed
         let img: ImageBuffer<Luma<u8>, Vec<u8>> =
-            ImageBuffer::from_raw(self.width as u32, self.height as u32, img_data)
+            ImageBuffer::from_raw(self.width as u32, self.height as u32, self.data.clone())
                 .ok_or(ImplError::ImageCreation)?;
 
         img.save(filename).map_err(|_| ImplError::ImageSaving)?;
-        println!("Saved PNG to {}", filename.display());
+        info!("Saved PNG to {}", filename.display());
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn from_strided_drops_row_padding()
+    {
+        let (width, height, stride) = (13, 4, 32);
+
+        // Padding bytes are 0xFF so any that leak into the frame show up
+        let mut data = vec![0xFF; stride * height];
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                data[y * stride + x] = (y * width + x) as u8;
+            }
+        }
+
+        let frame = MonoFrame::from_strided(&data, stride, width as u16, height as u16);
+
+        assert_eq!(frame.width, 13);
+        assert_eq!(frame.height, 4);
+        assert_eq!(frame.data, (0..(width * height) as u8).collect::<Vec<u8>>());
+    }
+}
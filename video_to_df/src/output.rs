@@ -1,7 +1,17 @@
 use std::{
+    collections::HashMap,
     fs,
+    hash::{
+        DefaultHasher,
+        Hash,
+        Hasher,
+    },
     io::Write,
-    path::Path,
+    ops::Range,
+    path::{
+        Path,
+        PathBuf,
+    },
     sync::Mutex,
 };
 
@@ -21,182 +31,840 @@ use crate::{
     Config,
     ImplError,
     MonoFrame,
+    PackConfig,
+    ProjectConfig,
     Result,
-    ffmpeg,
-    sdf,
+    SourceConfig,
+    TileGrid,
+    evaluate::{
+        DensityFunction,
+        DensityFunctionLoader,
+    },
+    layout::{
+        self,
+        GridLayout,
+    },
+    log::{
+        detail,
+        info,
+    },
+    pack,
+    playback,
+    preset,
+    process::{
+        self,
+        FrameProcessor,
+    },
+    source::{
+        self,
+        FrameSource,
+    },
+    timeline::{
+        self,
+        Timeline,
+    },
 };
 
+/// File name of the grid DF, without the extension
+const GRID_DF_NAME: &str = "all_frames";
+
+/// Grid spirals longer than this many times what the frames need packed into a square get a
+/// warning, Hilbert curves alone can reach about 4
+const SPARSE_GRID_WARN_RATIO: usize = 8;
+
+/// Grid spirals longer than this many times what the frames need packed into a square are
+/// refused once they're past `SPARSE_GRID_MAX_LEN` entries
+const SPARSE_GRID_MAX_RATIO: usize = 64;
+
+/// Short spirals stay cheap however much of them is padding
+const SPARSE_GRID_WARN_LEN: usize = 1 << 16;
+const SPARSE_GRID_MAX_LEN: usize = 1 << 22;
+
 pub fn write_projects_from_config(
-    frames: Vec<MonoFrame>,
     config: Config,
+    dry_run: bool,
+) -> Result<()>
+{
+    write_projects_streamed(&config, false, dry_run)
+}
+
+pub fn test_projects_from_config(
+    config: Config,
+    dry_run: bool,
 ) -> Result<()>
 {
-    let num_projects = config.projects.len();
-    fs::create_dir_all(&config.output_root_dir)
-        .map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
-    for n in 0..num_projects
+    write_projects_streamed(&config, true, dry_run)
+}
+
+/// Renders each project's grid DF over the region, sampling the files a previous `run` wrote
+pub fn preview_projects_from_config(
+    config: Config,
+    origin: (isize, isize),
+    size: (u16, u16),
+) -> Result<()>
+{
+    let loader = DensityFunctionLoader::new(&config.output_root_dir, config.pack.as_ref());
+
+    for (n, project_config) in config.selected_projects()
     {
-        write_project_n_from_config(&frames, n, &config)?;
+        let preview_name = project_config.name.clone().unwrap_or_else(|| (n + 1).to_string());
+        let grid_df = loader.load(&grid_id(project_config))?;
+        loader
+            .render(&grid_df, origin, size)?
+            .save_as(&config.output_root_dir.join(format!("preview_{}.png", preview_name)))?;
     }
     Ok(())
 }
 
-fn write_project_n_from_config(
-    frames: &Vec<MonoFrame>,
-    n: usize,
+/// Decodes each distinct source once, streaming bounded batches of frames through every project
+/// reading from it
+///
+/// A dry run stops before the first file gets written, once the config and sources are checked
+fn write_projects_streamed(
     config: &Config,
+    is_test: bool,
+    dry_run: bool,
 ) -> Result<()>
 {
-    let project_config = config.projects.get(n).ok_or(ImplError::AccessProjectConfig)?;
-
-    let border_width = project_config.border_width as usize;
-
-    let frame_dim =
-        (frames[0].width as usize + border_width * 2, frames[0].height as usize + border_width * 2);
+    // Test previews stay in a plain folder, so only runs get zipped
+    let zip_pack = !is_test && config.pack.as_ref().is_some_and(|pack| pack.zip.unwrap_or(false));
+    let root_dir = match zip_pack
+    {
+        true => pack::staging_dir(&config.output_root_dir),
+        false => config.output_root_dir.clone(),
+    };
 
-    let root_dir = &config.output_root_dir;
+    // Every source is opened and checked before anything gets written, a dry run skips the
+    // passes collecting statistics over the frames
+    let mut source_runs = source_groups(config)
+        .into_iter()
+        .map(|(source, source_fps, projects)| {
+            SourceRun::open(config, source, source_fps, projects, &root_dir, is_test, dry_run)
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    let frame_dir = root_dir.join(&project_config.frame_dfs_dir);
+    if dry_run
+    {
+        for (n, output) in source_runs.iter().flat_map(|source_run| &source_run.outputs)
+        {
+            output.plan(*n).iter().for_each(|line| info!("{}", line));
+        }
+        if zip_pack
+        {
+            info!("Zipped into {}", pack::zip_path(&config.output_root_dir).display());
+        }
+        info!("Dry run, nothing was written");
+        return Ok(());
+    }
 
-    let grid_dir = root_dir.join(&project_config.grid_df_dir);
+    for (n, output) in source_runs.iter().flat_map(|source_run| &source_run.outputs)
+    {
+        output.plan(*n).iter().for_each(|line| detail!("{}", line));
+    }
 
-    let tp_dir = root_dir.join(&project_config.tp_dir);
+    // Removed again on every way out of here, the zip being the only thing that's kept
+    let _staging_dir = zip_pack.then(|| pack::StagingDir::create(&root_dir)).transpose()?;
+    fs::create_dir_all(&root_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
 
-    let index_start = match project_config.frame_start
+    if let Some(pack) = &config.pack
     {
-        None => 0,
-        Some(frame_start) => (frame_start.get() - 1) as usize,
-    };
-    let index_end = match project_config.frame_end
+        pack::write_pack_mcmeta(&root_dir, pack)?;
+    }
+
+    for source_run in &mut source_runs
     {
-        None => frames.len(),
-        Some(frame_start) => (frame_start.get() - 1) as usize,
-    };
+        source_run.stream()?;
+    }
 
-    if index_start.min(index_end) > frames.len()
+    if zip_pack
     {
-        return Err(
-            CliError::InvalidFrameRange((index_start + 1, index_end + 1), frames.len()).into()
-        );
+        pack::zip_dir(&root_dir, &pack::zip_path(&config.output_root_dir))?;
     }
+    Ok(())
+}
 
-    let index_range = (index_start, index_end);
+/// A source, the frame rate it's read at and the projects reading it, each with its 0-based
+/// position in the config
+type SourceGroup<'a> = (&'a SourceConfig, Option<f64>, Vec<(usize, &'a ProjectConfig)>);
 
-    let frame_namespace =
-        create_df_namespace(&project_config.namespace, &project_config.frame_dfs_dir);
+/// Projects grouped by the source they read, in config order
+fn source_groups(config: &Config) -> Vec<SourceGroup<'_>>
+{
+    let mut groups: Vec<SourceGroup> = Vec::new();
+    // Sources are compared by their resolved paths, however the config spells them
+    let mut canonical_sources = Vec::new();
 
-    if project_config.make_frames
+    for (n, project_config) in config.selected_projects()
     {
-        write_json_frames_parallel(
-            frames,
-            frame_dim,
-            index_range,
-            border_width as u16,
-            project_config.border_color,
-            &frame_dir,
-        )?;
+        let (source, source_fps) = config.project_source(project_config);
+        let canonical_source = source::canonical_source(source);
+
+        match groups.iter_mut().zip(&canonical_sources).find(|(group, group_source)| {
+            **group_source == canonical_source && group.1 == source_fps
+        })
+        {
+            Some((group, _)) => group.2.push((n, project_config)),
+            None =>
+            {
+                groups.push((source, source_fps, vec![(n, project_config)]));
+                canonical_sources.push(canonical_source);
+            },
+        }
     }
+    groups
+}
 
-    if project_config.make_grid
+/// One decode of a source, shared by the outputs of every project reading from it
+struct SourceRun<'a>
+{
+    frames: Box<dyn FrameSource>,
+    /// Outputs along with their project's 0-based position in the config
+    outputs: Vec<(usize, ProjectOutput<'a>)>,
+}
+
+impl<'a> SourceRun<'a>
+{
+    fn open(
+        config: &'a Config,
+        source: &SourceConfig,
+        source_fps: Option<f64>,
+        projects: Vec<(usize, &'a ProjectConfig)>,
+        root_dir: &'a Path,
+        is_test: bool,
+        dry_run: bool,
+    ) -> Result<SourceRun<'a>>
     {
-        write_json_grid(index_range, frame_dim, &frame_namespace, &grid_dir)?;
+        // Needed up front to turn timestamps into the frame range to decode
+        let source_fps = source::source_frame_rate(source, source_fps)?;
+
+        let run_index_ranges = projects
+            .iter()
+            .map(|(_, project_config)| project_index_range(project_config, source_fps))
+            .collect::<Result<Vec<_>>>()?;
+
+        let index_ranges = match is_test
+        {
+            false => run_index_ranges.clone(),
+            true => projects
+                .iter()
+                .zip(&run_index_ranges)
+                .map(|((_, project_config), run_index_range)| {
+                    test_index_range(project_config, *run_index_range, source_fps)
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        let frames = source::open_frame_source(source, source::union_index_range(&index_ranges))?;
+        if frames.width() == 0 || frames.height() == 0
+        {
+            return Err(CliError::EmptySource.into());
+        }
+
+        // Outputs are checked against the config and the frame count before the statistics
+        // pass, so a mistake doesn't wait for a full decode of the source
+        let mut outputs = projects
+            .into_iter()
+            .zip(index_ranges)
+            .map(|((n, project_config), index_range)| {
+                let processor =
+                    FrameProcessor::new(project_config, (frames.width(), frames.height()))?;
+                // The test frame is previewed on its own, without resampling
+                let resample = if is_test { None } else { project_config.resample };
+                let timeline = Timeline::new(resample, index_range.0, source_fps)?;
+                let output = ProjectOutput::new(
+                    project_config,
+                    processor,
+                    timeline,
+                    index_range,
+                    root_dir,
+                    config.pack.as_ref(),
+                    is_test,
+                )?;
+                Ok((n, output))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Sources that know their length catch a range past their end before anything gets
+        // decoded, the rest once every frame was
+        if let Some(frame_count) = frames.frame_count()
+        {
+            for (_, output) in &outputs
+            {
+                output.check_frame_count(frame_count)?;
+                output.grid_layout(frame_count)?;
+            }
+        }
+
+        // Statistics cover every frame the project runs over, even when testing a single frame
+        let awaits_letterbox: Vec<bool> =
+            outputs.iter().map(|(_, output)| output.processor.awaits_letterbox()).collect();
+        if !dry_run
+        {
+            let mut statistics_ranges: Vec<_> = outputs
+                .iter_mut()
+                .map(|(_, output)| &mut output.processor)
+                .zip(run_index_ranges)
+                .collect();
+            process::collect_statistics(source, &mut statistics_ranges)?;
+        }
+
+        // A dry run sizes the frames before the letterbox is removed
+        for ((_, output), awaits_letterbox) in outputs.iter_mut().zip(awaits_letterbox)
+        {
+            if awaits_letterbox
+            {
+                output.update_frame_dim()?;
+            }
+        }
+
+        Ok(SourceRun { frames, outputs })
     }
 
-    if project_config.make_tp
+    /// Decodes the source, writing every batch of frames through each project's output
+    fn stream(&mut self) -> Result<()>
     {
-        write_tp_functions(index_range, frame_dim, project_config.tp_height, &tp_dir)?;
+        for (_, output) in &self.outputs
+        {
+            output.create_dirs()?;
+        }
+
+        loop
+        {
+            let batch = self.frames.next_batch(source::frame_batch_size())?;
+            if batch.is_empty()
+            {
+                break;
+            }
+            for (_, output) in &self.outputs
+            {
+                output.write_batch(&batch)?;
+            }
+            detail!("Decoded {} frames", self.frames.frames_decoded());
+        }
+
+        for (_, output) in &self.outputs
+        {
+            output.finish(self.frames.frames_decoded())?;
+        }
+        Ok(())
     }
-    Ok(())
 }
 
-fn create_df_namespace(
-    namespace: &str,
-    relative_path: &Path,
-) -> String
+/// 0-based, end exclusive range of the source frames the project's inclusive 1-based
+/// `frame_start..=frame_end` covers
+fn project_index_range(
+    project_config: &ProjectConfig,
+    source_fps: Option<f64>,
+) -> Result<(usize, Option<usize>)>
 {
-    let relative_part = relative_path.strip_prefix("./").unwrap().to_string_lossy();
+    let index_start = match &project_config.frame_start
+    {
+        None => 0,
+        Some(frame_start) => timeline::position_index(frame_start, source_fps)?,
+    };
+    let index_end = match &project_config.frame_end
+    {
+        None => None,
+        // The end frame is shown too
+        Some(frame_end) => Some(timeline::position_index(frame_end, source_fps)? + 1),
+    };
 
-    format!("{}:{}/", namespace, relative_part)
+    if let Some(index_end) = index_end.filter(|index_end| *index_end <= index_start)
+    {
+        return Err(CliError::InvertedFrameRange((index_start + 1, index_end)).into());
+    }
+    Ok((index_start, index_end))
 }
 
-pub fn test_projects_from_config(
-    frames: Vec<MonoFrame>,
-    config: Config,
-) -> Result<()>
+/// Range of just the test frame, which has to be one of the frames `run` outputs (the first
+/// one by default)
+fn test_index_range(
+    project_config: &ProjectConfig,
+    run_index_range: (usize, Option<usize>),
+    source_fps: Option<f64>,
+) -> Result<(usize, Option<usize>)>
 {
-    let num_projects = config.projects.len();
-    fs::create_dir_all(&config.output_root_dir)
-        .map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
-    for n in 0..num_projects
+    let test_frame_index = match &project_config.test_frame
+    {
+        None => run_index_range.0,
+        Some(test_frame) => timeline::position_index(test_frame, source_fps)?,
+    };
+
+    let (index_start, index_end) = run_index_range;
+    if test_frame_index < index_start
+        || index_end.is_some_and(|index_end| test_frame_index >= index_end)
     {
-        test_project_n_from_config(&frames, n, &config)?;
+        return Err(CliError::TestFrameOutsideRange(
+            test_frame_index + 1,
+            (index_start + 1, index_end),
+        )
+        .into());
     }
-    Ok(())
+    Ok((test_frame_index, Some(test_frame_index + 1)))
 }
 
-fn test_project_n_from_config(
-    frames: &Vec<MonoFrame>,
-    n: usize,
-    config: &Config,
-) -> Result<()>
+/// Everything needed to write a single project's output while frames stream through
+struct ProjectOutput<'a>
 {
-    let project_config = config.projects.get(n).ok_or(ImplError::AccessProjectConfig)?;
+    project_config: &'a ProjectConfig,
+    processor: FrameProcessor,
+    timeline: Timeline,
+    index_range: (usize, Option<usize>),
+    frame_dim: (usize, usize),
+    root_dir: &'a Path,
+    pack: Option<&'a PackConfig>,
+    frame_dir: PathBuf,
+    grid_dir: PathBuf,
+    tp_dir: PathBuf,
+    function_dir: PathBuf,
+    frame_namespace: String,
+    tiling: Option<Tiling>,
+    /// `None` writes every frame, even when it repeats an earlier one
+    deduplicator: Option<Mutex<FrameDeduplicator>>,
+    is_test: bool,
+}
 
-    let border_width = project_config.border_width as usize;
+/// Frames split into tiles, each written as its own DF next to the frame DF composing them, the
+/// grid DF places the tiles itself
+struct Tiling
+{
+    columns: u16,
+    rows: u16,
+    /// Divides the frame size evenly, and with it the grid's cell pitch
+    tile_dim: (usize, usize),
+    frame_namespace: String,
+}
 
-    let frame_dim =
-        (frames[0].width as usize + border_width * 2, frames[0].height as usize + border_width * 2);
+/// Frame files written so far, so repeated frames can point at the first copy instead
+#[derive(Default)]
+struct FrameDeduplicator
+{
+    /// Content hash of the gradated frame -> frame number of the first frame written with it,
+    /// repeats are compared byte for byte with its files read back from disk
+    frame_files: HashMap<u64, usize>,
+    /// Frame number -> size of its files, for every frame that got its own files
+    file_sizes: HashMap<usize, usize>,
+    /// Frame number -> frame number of the earlier file showing the same frame
+    repeats: HashMap<usize, usize>,
+    bytes_saved: usize,
+}
 
-    let root_dir = &config.output_root_dir;
-    let frame_dir = root_dir.join(&project_config.frame_dfs_dir);
-    let grid_dir = root_dir.join(&project_config.grid_df_dir);
-    let tp_dir = root_dir.join(&project_config.tp_dir);
+/// Where a frame's hash was seen before
+enum HashMatch
+{
+    None,
+    /// A frame from an earlier batch
+    Written(u64),
+    /// An earlier frame in the same batch, by its position
+    Batch(usize),
+}
 
-    let test_frame_index = match project_config.test_frame
+impl<'a> ProjectOutput<'a>
+{
+    fn new(
+        project_config: &'a ProjectConfig,
+        processor: FrameProcessor,
+        timeline: Timeline,
+        index_range: (usize, Option<usize>),
+        root_dir: &'a Path,
+        pack: Option<&'a PackConfig>,
+        is_test: bool,
+    ) -> Result<ProjectOutput<'a>>
     {
-        None => 0,
-        Some(test_frame) => (test_frame.get() - 1) as usize,
-    };
+        let density_function_dir =
+            pack::density_function_dir(root_dir, pack, &project_config.namespace);
+        let function_dir = pack::function_dir(root_dir, pack, &project_config.namespace);
 
-    let target_frame = frames
-        .get(test_frame_index)
-        .ok_or(CliError::InvalidTestFrame(test_frame_index + 1, frames.len()))?;
+        let frame_dir = density_function_dir.join(&project_config.frame_dfs_dir);
 
-    let index_range = (test_frame_index, test_frame_index + 1);
+        let frame_namespace =
+            create_df_namespace(&project_config.namespace, &project_config.frame_dfs_dir);
 
-    target_frame.save_as(&root_dir.join(&format!("test_frame_{}.png", test_frame_index + 1)))?;
+        if project_config.playback.is_some()
+        {
+            if pack.is_none()
+            {
+                return Err(CliError::PlaybackWithoutPack.into());
+            }
+            if !project_config.make_tp
+            {
+                return Err(CliError::PlaybackWithoutTp.into());
+            }
+        }
 
-    sdf::binary_sdf(
-        &target_frame.add_border(project_config.border_width, project_config.border_color),
-    )
-    .save_as(&root_dir.join(&format!("gradated_test_frame_{}.png", test_frame_index + 1)))?;
+        let mut output = ProjectOutput {
+            project_config,
+            processor,
+            timeline,
+            index_range,
+            frame_dim: (0, 0),
+            root_dir,
+            pack,
+            frame_dir,
+            grid_dir: density_function_dir.join(&project_config.grid_df_dir),
+            tp_dir: function_dir.join(&project_config.tp_dir),
+            function_dir,
+            frame_namespace,
+            tiling: None,
+            deduplicator: project_config
+                .dedupe_frames
+                .unwrap_or(false)
+                .then(|| Mutex::new(FrameDeduplicator::default())),
+            is_test,
+        };
+
+        // The letterbox crop decides the frame size, see `update_frame_dim`
+        if !output.processor.awaits_letterbox()
+        {
+            output.update_frame_dim()?;
+        }
+        Ok(output)
+    }
+
+    /// Sizes the bordered frames and their tiles after the processor's prepared frames
+    fn update_frame_dim(&mut self) -> Result<()>
+    {
+        let border_width = self.project_config.border_width as usize;
 
-    let frame_namespace =
-        create_df_namespace(&project_config.namespace, &project_config.frame_dfs_dir);
+        let (prepared_width, prepared_height) = self.processor.prepared_dim();
+
+        let frame_dim = (
+            prepared_width as usize + border_width * 2,
+            prepared_height as usize + border_width * 2,
+        );
+
+        // Frame sizes are u16 everywhere past this point
+        if frame_dim.0 > u16::MAX as usize || frame_dim.1 > u16::MAX as usize
+        {
+            return Err(CliError::BorderTooWide(
+                self.project_config.border_width,
+                (prepared_width, prepared_height),
+            )
+            .into());
+        }
 
-    if project_config.make_frames
+        self.tiling = match self.project_config.tiles
+        {
+            None => None,
+            Some(tiles) => Some(Tiling::new(tiles, frame_dim, self.frame_namespace.clone())?),
+        };
+        self.frame_dim = frame_dim;
+        Ok(())
+    }
+
+    /// What the project writes where, `n` being its 0-based position in the config
+    fn plan(
+        &self,
+        n: usize,
+    ) -> Vec<String>
     {
-        write_json_frames_parallel(
+        let frames = match (self.is_test, self.index_range.1)
+        {
+            (true, _) => format!("test frame {}", self.index_range.0 + 1),
+            (false, Some(index_end)) =>
+            {
+                format!("frames {} to {}", self.index_range.0 + 1, index_end)
+            },
+            (false, None) => format!("frames {} to the last frame", self.index_range.0 + 1),
+        };
+
+        // A dry run doesn't look for the letterbox
+        let letterbox = match self.processor.awaits_letterbox()
+        {
+            true => " before removing the letterbox",
+            false => "",
+        };
+
+        let mut plan = vec![format!(
+            "Project {} ({}): {}, {}x{} frame DFs{}",
+            n + 1,
+            self.project_config.label(),
             frames,
-            frame_dim,
-            index_range,
-            border_width as u16,
-            project_config.border_color,
-            &frame_dir,
-        )?;
+            self.frame_dim.0,
+            self.frame_dim.1,
+            letterbox
+        )];
+        if self.project_config.make_frames
+        {
+            plan.push(format!("  Frames into {}", self.frame_dir.display()));
+        }
+        if self.project_config.make_grid
+        {
+            plan.push(format!(
+                "  Grid into {}",
+                self.grid_dir.join(format!("{}.json", GRID_DF_NAME)).display()
+            ));
+        }
+        if self.project_config.make_tp
+        {
+            plan.push(format!("  Tp functions into {}", self.tp_dir.display()));
+        }
+        plan
     }
 
-    if project_config.make_grid
+    fn create_dirs(&self) -> Result<()>
     {
-        write_json_grid(index_range, frame_dim, &frame_namespace, &grid_dir)?;
+        if self.project_config.make_frames
+        {
+            fs::create_dir_all(&self.frame_dir)
+                .map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+        }
+        Ok(())
     }
 
-    if project_config.make_tp
+    fn contains(
+        &self,
+        index: usize,
+    ) -> bool
     {
-        write_tp_functions(index_range, frame_dim, project_config.tp_height, &tp_dir)?;
+        source::range_contains(self.index_range, index)
     }
 
-    Ok(())
+    fn write_batch(
+        &self,
+        batch: &[(usize, MonoFrame)],
+    ) -> Result<()>
+    {
+        let frames: Vec<&(usize, MonoFrame)> =
+            batch.iter().filter(|(index, _)| self.contains(*index)).collect();
+
+        if self.is_test
+        {
+            for (index, frame) in &frames
+            {
+                let prepared = self.processor.prepare(frame);
+
+                prepared.save_as(&self.root_dir.join(format!("test_frame_{}.png", index + 1)))?;
+
+                info!(
+                    "Threshold for test frame {}: {}",
+                    index + 1,
+                    self.processor.threshold_for(&prepared)
+                );
+
+                self.processor.gradate(&prepared).save_as(
+                    &self.root_dir.join(format!("gradated_test_frame_{}.png", index + 1)),
+                )?;
+            }
+        }
+
+        if self.project_config.make_frames
+        {
+            // Resampled frames are dropped or written under every output index that shows them
+            let frames: Vec<(Range<usize>, &MonoFrame)> = frames
+                .iter()
+                .map(|(index, frame)| (self.timeline.output_indices(*index), frame))
+                .filter(|(output_indices, _)| !output_indices.is_empty())
+                .collect();
+
+            match &self.deduplicator
+            {
+                None => write_json_frames_parallel(
+                    &frames,
+                    self.frame_dim,
+                    self.tiling.as_ref(),
+                    &self.processor,
+                    &self.frame_dir,
+                )?,
+                Some(deduplicator) => write_json_frames_deduplicated(
+                    &frames,
+                    self.frame_dim,
+                    self.tiling.as_ref(),
+                    &self.processor,
+                    deduplicator,
+                    &self.frame_dir,
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the range against the number of frames in the source
+    fn check_frame_count(
+        &self,
+        frame_count: usize,
+    ) -> Result<()>
+    {
+        let index_start = self.index_range.0;
+        let index_end = self.index_range.1.unwrap_or(frame_count);
+
+        if frame_count == 0
+        {
+            return Err(CliError::EmptySource.into());
+        }
+
+        if self.is_test
+        {
+            if index_start >= frame_count
+            {
+                return Err(CliError::InvalidTestFrame(index_start + 1, frame_count).into());
+            }
+        }
+        else if index_start >= frame_count || index_end > frame_count
+        {
+            return Err(CliError::InvalidFrameRange(
+                (index_start + 1, self.index_range.1),
+                frame_count,
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Output frame range and where its frames go in the grid, refusing grids whose spiral
+    /// would be almost all padding
+    fn grid_layout(
+        &self,
+        frame_count: usize,
+    ) -> Result<((usize, usize), GridLayout)>
+    {
+        let index_end = self.index_range.1.unwrap_or(frame_count);
+        let index_range = self.timeline.output_range(index_end);
+
+        let cell_count = index_range.1 - index_range.0;
+        let layout = GridLayout::new(
+            self.frame_dim,
+            self.project_config.grid_spacing,
+            self.project_config.grid_order,
+            self.project_config.grid_wrap,
+            cell_count,
+        );
+
+        // The grid DF lists every spiral position up to the farthest cell, a long row or column
+        // pads it with millions of out of bounds entries
+        if self.project_config.make_grid
+        {
+            let tiles = self.tiling.as_ref().map(Tiling::tile_counts);
+            let spiral_len = layout.spiral_len(cell_count, tiles);
+            if spiral_len > SPARSE_GRID_MAX_LEN
+                && spiral_len
+                    > SPARSE_GRID_MAX_RATIO * cell_count * layout.spiral_cells_per_frame(tiles)
+            {
+                return Err(CliError::SparseGrid(cell_count, spiral_len).into());
+            }
+        }
+        Ok((index_range, layout))
+    }
+
+    /// Writes the outputs that need the full frame count, once every frame has streamed through
+    fn finish(
+        &self,
+        frames_decoded: usize,
+    ) -> Result<()>
+    {
+        // Only sources that don't know their length get here with a range past their end
+        self.check_frame_count(frames_decoded)?;
+        let (index_range, layout) = self.grid_layout(frames_decoded)?;
+
+        let cell_count = index_range.1 - index_range.0;
+        let tiles = self.tiling.as_ref().map(Tiling::tile_counts);
+        let spiral_len = layout.spiral_len(cell_count, tiles);
+        if self.project_config.make_grid
+            && spiral_len > SPARSE_GRID_WARN_LEN
+            && spiral_len
+                > SPARSE_GRID_WARN_RATIO * cell_count * layout.spiral_cells_per_frame(tiles)
+        {
+            info!(
+                "Warning: the grid spirals over {} cells to place {} frames, a 'grid_wrap' closer \
+                 to a square keeps it smaller",
+                spiral_len, cell_count
+            );
+        }
+
+        let deduplicator =
+            self.deduplicator.as_ref().map(|deduplicator| deduplicator.lock().unwrap());
+        if let Some(deduplicator) = &deduplicator
+        {
+            let repeat_count = deduplicator.repeats.len();
+            info!(
+                "Deduplicated frames: {} of {} point at an earlier frame, saved {:.1} MiB",
+                repeat_count,
+                deduplicator.file_sizes.len() + repeat_count,
+                deduplicator.bytes_saved as f64 / (1024.0 * 1024.0)
+            );
+        }
+
+        let no_repeats = HashMap::new();
+        let repeats =
+            deduplicator.as_ref().map_or(&no_repeats, |deduplicator| &deduplicator.repeats);
+
+        if self.project_config.make_grid
+        {
+            write_json_grid(
+                index_range,
+                &layout,
+                &self.frame_namespace,
+                self.tiling.as_ref(),
+                repeats,
+                &self.grid_dir,
+            )?;
+        }
+
+        if self.project_config.make_tp
+        {
+            write_tp_functions(index_range, &layout, self.project_config.tp_height, &self.tp_dir)?;
+        }
+
+        if self.is_test && self.project_config.make_frames
+        {
+            // Read back from the written file, exactly what the mod will sample for the frame
+            let frame_number = index_range.0 + 1;
+            let loader = DensityFunctionLoader::new(self.root_dir, self.pack);
+            let frame_df = loader.load(&format!("{}{}", self.frame_namespace, frame_number))?;
+            loader
+                .render(&frame_df, (0, 0), (self.frame_dim.0 as u16, self.frame_dim.1 as u16))?
+                .save_as(&self.root_dir.join(format!("preview_test_frame_{}.png", frame_number)))?;
+        }
+
+        if let (Some(playback_config), Some(pack)) = (&self.project_config.playback, self.pack)
+        {
+            playback::write_playback_functions(
+                playback_config,
+                &self.project_config.namespace,
+                &create_df_namespace(&self.project_config.namespace, &self.project_config.tp_dir),
+                index_range,
+                &self.function_dir,
+                &pack::function_tag_dir(self.root_dir, pack),
+            )?;
+        }
+
+        if let Some(preset_config) = &self.project_config.world_preset
+        {
+            preset::write_world_preset(
+                preset_config,
+                &self.project_config.namespace,
+                &grid_id(self.project_config),
+                self.root_dir,
+                self.pack,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn grid_id(project_config: &ProjectConfig) -> String
+{
+    format!(
+        "{}{}",
+        create_df_namespace(&project_config.namespace, &project_config.grid_df_dir),
+        GRID_DF_NAME
+    )
+}
+
+pub fn create_df_namespace(
+    namespace: &str,
+    relative_path: &Path,
+) -> String
+{
+    let relative_part = relative_path.strip_prefix("./").unwrap_or(relative_path).to_string_lossy();
+
+    // Files straight in the namespace's folder, like the grid DF in `./`
+    if relative_part.is_empty()
+    {
+        return format!("{}:", namespace);
+    }
+
+    format!("{}:{}/", namespace, relative_part)
 }
 
 // fn write_json_frames(
@@ -208,7 +876,7 @@ fn test_project_n_from_config(
 //     output_dir: &Path,
 // ) -> Result<()>
 // {
-//     fs::create_dir_all(&output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+//     fs::create_dir_all(output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
 
 //     for (index, frame) in (index_range.0..index_range.1).zip(frames.iter().skip(index_range.0))
 //     {
@@ -239,41 +907,35 @@ fn test_project_n_from_config(
 // }
 
 fn write_json_frames_parallel(
-    frames: &Vec<MonoFrame>,
+    frames: &[(Range<usize>, &MonoFrame)],
     frame_dim: (usize, usize),
-    index_range: (usize, usize),
-    border_width: u16,
-    border_color: u8,
+    tiling: Option<&Tiling>,
+    processor: &FrameProcessor,
     output_dir: &Path,
 ) -> Result<()>
 {
-    fs::create_dir_all(&output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
-
     // Store ImplError directly instead of Box<dyn Error>
     let errors: Mutex<Vec<ImplError>> = Mutex::new(Vec::new());
 
     // Process frames in parallel
-    (index_range.0..index_range.1)
-        .into_par_iter()
-        .zip(frames.par_iter().skip(index_range.0))
-        .for_each(|(index, frame)| {
-            match process_single_frame(
-                frame,
-                frame_dim,
-                index,
-                border_width,
-                border_color,
-                output_dir,
-            )
+    frames.par_iter().for_each(|(output_indices, frame)| {
+        match process_single_frame(
+            frame,
+            frame_dim,
+            tiling,
+            output_indices.clone(),
+            processor,
+            output_dir,
+        )
+        {
+            Ok(()) =>
+            {},
+            Err(e) =>
             {
-                Ok(()) =>
-                {},
-                Err(e) =>
-                {
-                    errors.lock().unwrap().push(e);
-                },
-            }
-        });
+                errors.lock().unwrap().push(e);
+            },
+        }
+    });
 
     // Check if any errors occurred
     let errors = errors.into_inner().unwrap();
@@ -288,13 +950,207 @@ fn write_json_frames_parallel(
 fn process_single_frame(
     frame: &MonoFrame,
     frame_dim: (usize, usize),
-    index: usize,
-    border_width: u16,
-    border_color: u8,
+    tiling: Option<&Tiling>,
+    output_indices: Range<usize>,
+    processor: &FrameProcessor,
     output_dir: &Path,
 ) -> std::result::Result<(), ImplError>
 {
-    let grad_frame = sdf::binary_sdf(&frame.add_border(border_width, border_color));
+    let grad_frame = processor.process(frame);
+
+    for index in output_indices
+    {
+        write_frame_files(
+            &frame_json_files(&grad_frame, frame_dim, tiling, index + 1)?,
+            output_dir,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Like `write_json_frames_parallel`, but frames repeating an earlier frame aren't written again
+///
+/// Frames are only compressed and encoded once their hash says they're new, a hash seen before
+/// is only trusted once the bytes match the files written for it too
+fn write_json_frames_deduplicated(
+    frames: &[(Range<usize>, &MonoFrame)],
+    frame_dim: (usize, usize),
+    tiling: Option<&Tiling>,
+    processor: &FrameProcessor,
+    deduplicator: &Mutex<FrameDeduplicator>,
+    output_dir: &Path,
+) -> Result<()>
+{
+    let mut deduplicator = deduplicator.lock().unwrap();
+
+    let grad_frames: Vec<(u64, MonoFrame)> = frames
+        .par_iter()
+        .map(|(_, frame)| {
+            let grad_frame = processor.process(frame);
+            let mut hasher = DefaultHasher::new();
+            grad_frame.data.hash(&mut hasher);
+            (hasher.finish(), grad_frame)
+        })
+        .collect();
+
+    // Frames are claimed in order, so the earliest copy of a frame is the one that gets written
+    let mut batch_hashes = HashMap::new();
+    let hash_matches: Vec<HashMatch> = grad_frames
+        .iter()
+        .enumerate()
+        .map(|(i, (hash, _))| {
+            if deduplicator.frame_files.contains_key(hash)
+            {
+                return HashMatch::Written(*hash);
+            }
+            match batch_hashes.get(hash)
+            {
+                Some(&first) => HashMatch::Batch(first),
+                None =>
+                {
+                    batch_hashes.insert(*hash, i);
+                    HashMatch::None
+                },
+            }
+        })
+        .collect();
+
+    // Frame number of the earlier file each frame repeats, `None` for frames needing their own
+    let repeated_files = hash_matches
+        .par_iter()
+        .zip(&grad_frames)
+        .map(|(hash_match, (_, grad_frame))| match hash_match
+        {
+            HashMatch::None => Ok(None),
+            HashMatch::Batch(first) =>
+            {
+                let is_repeat = grad_frames[*first].1.data == grad_frame.data;
+                Ok(is_repeat.then_some(frames[*first].0.start + 1))
+            },
+            HashMatch::Written(hash) =>
+            {
+                let frame_file = deduplicator.frame_files[hash];
+                let is_repeat = written_frame_matches(grad_frame, tiling, frame_file, output_dir)?;
+                Ok(is_repeat.then_some(frame_file))
+            },
+        })
+        .collect::<std::result::Result<Vec<_>, ImplError>>()?;
+
+    // Resampled frames shown under several indices also only get one file, under the first
+    let written_frames = repeated_files
+        .par_iter()
+        .enumerate()
+        .filter(|(_, repeated_file)| repeated_file.is_none())
+        .map(|(i, _)| {
+            let frame_number = frames[i].0.start + 1;
+            let frame_files = frame_json_files(&grad_frames[i].1, frame_dim, tiling, frame_number)?;
+            write_frame_files(&frame_files, output_dir)?;
+
+            let file_size = frame_files.iter().map(|(_, frame_json)| frame_json.len()).sum();
+            Ok((i, frame_number, file_size))
+        })
+        .collect::<std::result::Result<Vec<_>, ImplError>>()?;
+
+    for (i, frame_number, file_size) in written_frames
+    {
+        deduplicator.file_sizes.insert(frame_number, file_size);
+        // A frame only sharing its hash with an earlier one keeps its own file, but the hash
+        // stays with the earlier frame
+        deduplicator.frame_files.entry(grad_frames[i].0).or_insert(frame_number);
+    }
+
+    for ((output_indices, _), repeated_file) in frames.iter().zip(repeated_files)
+    {
+        let mut repeated_indices = output_indices.clone();
+        let frame_file = repeated_file.unwrap_or_else(|| repeated_indices.next().unwrap() + 1);
+        let file_size = deduplicator.file_sizes[&frame_file];
+
+        for index in repeated_indices
+        {
+            deduplicator.repeats.insert(index + 1, frame_file);
+            deduplicator.bytes_saved += file_size;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the files written for `frame_number` show the gradated frame, read back tile by tile
+/// when tiled
+fn written_frame_matches(
+    grad_frame: &MonoFrame,
+    tiling: Option<&Tiling>,
+    frame_number: usize,
+    output_dir: &Path,
+) -> std::result::Result<bool, ImplError>
+{
+    let frame_images = match tiling
+    {
+        None => vec![(frame_number.to_string(), grad_frame.clone())],
+        Some(tiling) => tiling.crop_tiles(grad_frame, frame_number),
+    };
+
+    for (file_name, frame_image) in frame_images
+    {
+        let written_image = DensityFunction::read(&output_dir.join(format!("{}.json", file_name)))
+            .map_err(|e| ImplError::FileReadBack(format!("{}", e)))?
+            .frame();
+        if written_image.is_none_or(|written_image| written_image.data != frame_image.data)
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// A frame's DF files as (file name, JSON), one per tile plus the composing one when tiled
+fn frame_json_files(
+    grad_frame: &MonoFrame,
+    frame_dim: (usize, usize),
+    tiling: Option<&Tiling>,
+    frame_number: usize,
+) -> std::result::Result<Vec<(String, String)>, ImplError>
+{
+    let Some(tiling) = tiling
+    else
+    {
+        return Ok(vec![(
+            format!("{}.json", frame_number),
+            frame_json_string(grad_frame, frame_dim)?,
+        )]);
+    };
+
+    let mut frame_files = Vec::with_capacity(tiling.columns as usize * tiling.rows as usize + 1);
+    for (tile_name, tile) in tiling.crop_tiles(grad_frame, frame_number)
+    {
+        frame_files
+            .push((format!("{}.json", tile_name), frame_json_string(&tile, tiling.tile_dim)?));
+    }
+
+    frame_files
+        .push((format!("{}.json", frame_number), tiling.composing_json_string(frame_number)?));
+    Ok(frame_files)
+}
+
+fn write_frame_files(
+    frame_files: &[(String, String)],
+    output_dir: &Path,
+) -> std::result::Result<(), ImplError>
+{
+    for (file_name, frame_json_string) in frame_files
+    {
+        fs::write(output_dir.join(file_name), frame_json_string)
+            .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+    }
+    Ok(())
+}
+
+fn frame_json_string(
+    grad_frame: &MonoFrame,
+    frame_dim: (usize, usize),
+) -> std::result::Result<String, ImplError>
+{
     let deflated_grad_frame =
         compress_zlib(&grad_frame.data).map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
     let encoded_deflated_grad_frame_data = general_purpose::STANDARD.encode(&deflated_grad_frame);
@@ -314,185 +1170,486 @@ fn process_single_frame(
         }
     );
 
-    let frame_json_string = serde_json::to_string_pretty(&frame_json)
-        .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))?;
+    serde_json::to_string_pretty(&frame_json)
+        .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))
+}
 
-    fs::write(output_dir.join(&format!("{}.json", index + 1)), &frame_json_string)
-        .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+impl Tiling
+{
+    fn new(
+        tiles: TileGrid,
+        frame_dim: (usize, usize),
+        frame_namespace: String,
+    ) -> Result<Tiling>
+    {
+        let (columns, rows) = (tiles.columns.get(), tiles.rows.get());
 
-    Ok(())
+        // The grid steps by a tile, so tiles have to divide the frame for every frame's tiles
+        // to start on its cell's corner
+        if !frame_dim.0.is_multiple_of(columns as usize)
+            || !frame_dim.1.is_multiple_of(rows as usize)
+        {
+            return Err(CliError::InvalidTiles((columns, rows), frame_dim).into());
+        }
+        let tile_dim = (frame_dim.0 / columns as usize, frame_dim.1 / rows as usize);
+
+        Ok(Tiling {
+            columns,
+            rows,
+            tile_dim,
+            frame_namespace,
+        })
+    }
+
+    /// (columns, rows)
+    fn tile_counts(&self) -> (usize, usize)
+    {
+        (self.columns as usize, self.rows as usize)
+    }
+
+    /// The frame's tiles with their names, in row major order like `tile_names`
+    fn crop_tiles(
+        &self,
+        grad_frame: &MonoFrame,
+        frame_number: usize,
+    ) -> Vec<(String, MonoFrame)>
+    {
+        let (tile_width, tile_height) = (self.tile_dim.0 as u16, self.tile_dim.1 as u16);
+        (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                (
+                    self.tile_name(frame_number, column, row),
+                    grad_frame.crop(
+                        column * tile_width,
+                        row * tile_height,
+                        tile_width,
+                        tile_height,
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    /// Names of the frame's tiles in row major order, the order `GridLayout::spiral_cells`
+    /// places them in
+    fn tile_names(
+        &self,
+        frame_number: usize,
+    ) -> impl Iterator<Item = String> + '_
+    {
+        (0..self.rows).flat_map(move |row| {
+            (0..self.columns).map(move |column| self.tile_name(frame_number, column, row))
+        })
+    }
+
+    fn tile_name(
+        &self,
+        frame_number: usize,
+        column: u16,
+        row: u16,
+    ) -> String
+    {
+        format!("{}_{}_{}", frame_number, column, row)
+    }
+
+    /// Grid placing the tiles side by side from 0 0, cells are listed in the grid's spiral
+    /// order so the ones outside the tiles are left out of bounds
+    ///
+    /// This is for sampling the frame on its own, e.g. the test frame preview. The grid DF
+    /// doesn't reference it: a grid inside one of its cells only lines up if moredfs passes
+    /// cells coordinates relative to their corner, and with world coordinates every frame but
+    /// the one at 0 0 would fall outside its tiles. Listing the tiles in the grid DF directly
+    /// works either way
+    fn composing_json_string(
+        &self,
+        frame_number: usize,
+    ) -> std::result::Result<String, ImplError>
+    {
+        let ring = self.columns.max(self.rows) as usize - 1;
+
+        let mut grid_cell_args: Vec<serde_json::Value> = (0..(2 * ring + 1).pow(2))
+            .map(|i| {
+                let (x, z) = layout::index_to_spiral_coords(i);
+                if (0..self.columns as isize).contains(&x) && (0..self.rows as isize).contains(&z)
+                {
+                    json!(format!(
+                        "{}{}",
+                        self.frame_namespace,
+                        self.tile_name(frame_number, x as u16, z as u16)
+                    ))
+                }
+                else
+                {
+                    json!(256)
+                }
+            })
+            .collect();
+
+        while grid_cell_args.last().is_some_and(|arg| arg.is_number())
+        {
+            grid_cell_args.pop();
+        }
+
+        let frame_json = json!(
+            {
+                "type": "moredfs:gapped_grid_square_spiral",
+                "spacing": 0,
+                "x_size": self.tile_dim.0,
+                "z_size": self.tile_dim.1,
+                "out_of_bounds_argument": 256,
+                "grid_cell_args": grid_cell_args
+            }
+        );
+
+        serde_json::to_string_pretty(&frame_json)
+            .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))
+    }
 }
 
+/// Cell `n` of the grid shows frame number `index_range.0 + n + 1`, the same cell its tp
+/// function teleports to
+///
+/// Tiled frames are listed tile by tile in a grid of tile sized cells, so the grid only ever
+/// holds tessellations. Those wrap at a size dividing the cell pitch and sample the same
+/// whether moredfs passes cells world coordinates or ones relative to their corner
 fn write_json_grid(
     index_range: (usize, usize),
-    frame_dim: (usize, usize),
+    layout: &GridLayout,
     namespace: &str,
+    tiling: Option<&Tiling>,
+    repeats: &HashMap<usize, usize>,
     output_dir: &Path,
 ) -> Result<()>
 {
-    fs::create_dir_all(&output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+    fs::create_dir_all(output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+
+    // Gaps between tiled frames are padding tiles, see `GridLayout::spiral_cells`
+    let (spacing, cell_dim) = match tiling
+    {
+        None => (layout.spacing, layout.frame_dim),
+        Some(tiling) => (0, tiling.tile_dim),
+    };
     let frame_json = json!(
         {
             "type": "moredfs:gapped_grid_square_spiral",
-            "spacing": 1,
-            "x_size":  frame_dim.0,
-            "z_size": frame_dim.1,
+            "spacing": spacing,
+            "x_size":  cell_dim.0,
+            "z_size": cell_dim.1,
             "out_of_bounds_argument": 256,
-            "grid_cell_args": ((index_range.0+1)..index_range.1)
-                .map(|i| format!("{}{}", namespace,  i))
-                .collect::<Vec<_>>()
+            "grid_cell_args": layout.grid_cell_args(
+                (index_range.0..index_range.1).enumerate().map(|(cell, index)| {
+                    let frame_number = index + 1;
+                    let frame_file = repeats.get(&frame_number).copied().unwrap_or(frame_number);
+                    let cell_args = match tiling
+                    {
+                        None => vec![json!(format!("{}{}", namespace, frame_file))],
+                        Some(tiling) => tiling
+                            .tile_names(frame_file)
+                            .map(|tile_name| json!(format!("{}{}", namespace, tile_name)))
+                            .collect(),
+                    };
+                    (cell, cell_args)
+                }),
+                tiling.map(Tiling::tile_counts),
+                json!(256),
+            )
         }
     );
     let frame_json_string = serde_json::to_string_pretty(&frame_json)
         .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))?;
-    fs::write(output_dir.join("all_frames.json"), &frame_json_string)
+    fs::write(output_dir.join(format!("{}.json", GRID_DF_NAME)), &frame_json_string)
         .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
     Ok(())
 }
 
 fn write_tp_functions(
     index_range: (usize, usize),
-    frame_dim: (usize, usize),
+    layout: &GridLayout,
     tp_height: i16,
     output_dir: &Path,
 ) -> Result<()>
 {
-    fs::create_dir_all(&output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+    fs::create_dir_all(output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
 
-    for i in (index_range.0)..index_range.1
+    for (cell, index) in (index_range.0..index_range.1).enumerate()
     {
-        let (curr_x, curr_z) = index_to_spiral_coords(i);
-        let (curr_x, curr_z) = (
-            curr_x * 2 * frame_dim.0 as isize + frame_dim.0 as isize / 2,
-            curr_z * 2 * frame_dim.1 as isize + frame_dim.1 as isize / 2,
-        );
-        let tp_string = format!("tp @a {} {} {} 180 90", curr_x, tp_height, curr_z);
-        fs::write(output_dir.join(format!("{}.mcfunction", i + 1)), &tp_string)
-            .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+        fs::write(
+            output_dir.join(format!("{}.mcfunction", index + 1)),
+            tp_command(layout, cell, tp_height),
+        )
+        .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
     }
     Ok(())
 }
 
-fn index_to_spiral_coords(n: usize) -> (isize, isize)
+/// Teleports to the middle of the frame shown in the cell, looking straight down
+pub fn tp_command(
+    layout: &GridLayout,
+    cell: usize,
+    tp_height: i16,
+) -> String
 {
-    if n == 0
-    {
-        return (0, 0);
-    }
+    let (center_x, center_z) = layout.cell_center(cell);
+    format!("tp @a {} {} {} 180 90", center_x, tp_height, center_z)
+}
 
-    // Find which ring/layer we're in
-    let layer = ((((n as f64).sqrt() - 1.0) / 2.0).floor() as isize) + 1;
+fn compress_zlib(bytes: &[u8]) -> Result<Vec<u8>>
+{
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
+    let compressed_bytes =
+        encoder.finish().map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
+    Ok(compressed_bytes)
+}
 
-    // Find the starting index of this layer
-    let layer_start = (2 * layer - 1).pow(2);
+#[cfg(test)]
+mod tests
+{
+    use std::{
+        env,
+        num::NonZeroU16,
+    };
 
-    // Position within the layer
-    let pos_in_layer = n as isize - layer_start;
+    use serde_json::Value;
 
-    // Side length of current layer
-    let side_length = 2 * layer;
+    use super::*;
+    use crate::GridOrder;
 
-    // Determine which side of the square we're on and calculate coordinates
-    if pos_in_layer < side_length
+    fn project_config(
+        frame_start: Option<u32>,
+        frame_end: Option<u32>,
+    ) -> ProjectConfig
     {
-        // Right side, moving up
-        (layer, -layer + 1 + pos_in_layer)
+        let mut project_json = json!({
+            "border_width": 0,
+            "border_color": 0,
+            "namespace": "test",
+            "make_frames": true,
+            "frame_dfs_dir": "frames",
+            "make_grid": true,
+            "grid_df_dir": "grid",
+            "make_tp": true,
+            "tp_height": 64,
+            "tp_dir": "tp",
+        });
+        if let Some(frame_start) = frame_start
+        {
+            project_json["frame_start"] = json!(frame_start);
+        }
+        if let Some(frame_end) = frame_end
+        {
+            project_json["frame_end"] = json!(frame_end);
+        }
+        serde_json::from_value(project_json).unwrap()
     }
-    else if pos_in_layer < 2 * side_length
+
+    /// Writes the grid and tp functions for the range into a fresh directory
+    fn write_grid_and_tp(
+        test_name: &str,
+        index_range: (usize, usize),
+        layout: &GridLayout,
+    ) -> PathBuf
     {
-        // Top side, moving left
-        (layer - 1 - (pos_in_layer - side_length), layer)
+        let output_dir = env::temp_dir().join(format!("v2df_{}_{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&output_dir);
+
+        write_json_grid(index_range, layout, "test:", None, &HashMap::new(), &output_dir).unwrap();
+        write_tp_functions(index_range, layout, 64, &output_dir).unwrap();
+        output_dir
     }
-    else if pos_in_layer < 3 * side_length
+
+    /// Frame number the grid shows at each spiral position, `None` for the out of bounds filler
+    fn grid_frame_numbers(output_dir: &Path) -> Vec<Option<usize>>
     {
-        // Left side, moving down
-        (-layer, layer - 1 - (pos_in_layer - 2 * side_length))
+        let grid_json = fs::read_to_string(output_dir.join(format!("{}.json", GRID_DF_NAME)));
+        let grid: Value = serde_json::from_str(&grid_json.unwrap()).unwrap();
+
+        grid["grid_cell_args"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|arg| arg.as_str().map(|id| id.strip_prefix("test:").unwrap().parse().unwrap()))
+            .collect()
     }
-    else
+
+    /// The (x, z) a tp function teleports to, `None` when the frame has no tp function
+    fn tp_target(
+        output_dir: &Path,
+        frame_number: usize,
+    ) -> Option<(isize, isize)>
     {
-        // Bottom side, moving right
-        (-layer + 1 + (pos_in_layer - 3 * side_length), -layer)
+        let tp_string =
+            fs::read_to_string(output_dir.join(format!("{}.mcfunction", frame_number))).ok()?;
+        let coords: Vec<isize> =
+            tp_string.split(' ').skip(2).take(3).map(|coord| coord.parse().unwrap()).collect();
+        Some((coords[0], coords[2]))
     }
-}
-
-fn compress_zlib(bytes: &[u8]) -> Result<Vec<u8>>
-{
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(bytes).map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
-    let compressed_bytes =
-        encoder.finish().map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
-    Ok(compressed_bytes)
-}
 
-pub fn get_single_channel_frames<P>(video_path: P) -> Result<Vec<MonoFrame>>
-where
-    P: AsRef<Path>,
-{
-    ffmpeg::init().map_err(|e| ImplError::FFmpeg(e))?;
-
-    let mut input = ffmpeg::format::input(video_path.as_ref()).map_err(|e| ImplError::FFmpeg(e))?;
-
-    let video_stream =
-        input.streams().best(ffmpeg::media::Type::Video).ok_or(ImplError::FetchVideoStream)?;
-
-    let video_stream_index = video_stream.index();
-
-    let mut decoder = ffmpeg::codec::context::Context::from_parameters(video_stream.parameters())
-        .map_err(|e| ImplError::FFmpeg(e))?
-        .decoder()
-        .video()
-        .map_err(|e| ImplError::FFmpeg(e))?;
-
-    // Set up context to convert to monochromatic
-    let mut monochromatic_ctx = ffmpeg::software::scaling::context::Context::get(
-        decoder.format(),
-        decoder.width(),
-        decoder.height(),
-        ffmpeg::format::Pixel::GRAY8, // Single channel monochromatic
-        decoder.width(),
-        decoder.height(),
-        ffmpeg::software::scaling::flag::Flags::BILINEAR,
+    /// Every frame the grid shows has a tp function landing inside the cell showing it
+    fn assert_tp_matches_grid(
+        output_dir: &Path,
+        layout: &GridLayout,
     )
-    .map_err(|e| ImplError::FFmpeg(e))?;
-
-    let mut frames: Vec<MonoFrame> = vec![];
-
-    for (stream, packet) in input.packets()
     {
-        if stream.index() == video_stream_index
+        for (spiral_index, frame_number) in grid_frame_numbers(output_dir).into_iter().enumerate()
         {
-            decoder.send_packet(&packet).map_err(|e| ImplError::FFmpeg(e))?;
+            let Some(frame_number) = frame_number
+            else
+            {
+                continue;
+            };
+            let (x, z) = layout::index_to_spiral_coords(spiral_index);
+            let (pitch_x, pitch_z) = layout.cell_pitch();
+            let (origin_x, origin_z) = (x * pitch_x as isize, z * pitch_z as isize);
+
+            let (tp_x, tp_z) = tp_target(output_dir, frame_number).unwrap();
+            assert!((origin_x..origin_x + layout.frame_dim.0 as isize).contains(&tp_x));
+            assert!((origin_z..origin_z + layout.frame_dim.1 as isize).contains(&tp_z));
+        }
+    }
 
-            let mut decoded = ffmpeg::util::frame::video::Video::empty();
-            while decoder.receive_frame(&mut decoded).is_ok()
+    #[test]
+    fn repeated_frames_point_at_the_first_copy()
+    {
+        let tiles = TileGrid {
+            columns: NonZeroU16::new(2).unwrap(),
+            rows: NonZeroU16::new(1).unwrap(),
+        };
+        let tiling = Tiling::new(tiles, (4, 3), "test:".to_owned()).unwrap();
+
+        // Tiled frames are compared with their tiles when read back from an earlier batch
+        for (tiling, expected_files) in [
+            (None, vec!["1.json", "2.json"]),
+            (
+                Some(&tiling),
+                vec!["1.json", "1_0_0.json", "1_1_0.json", "2.json", "2_0_0.json", "2_1_0.json"],
+            ),
+        ]
+        {
+            let output_dir =
+                env::temp_dir().join(format!("v2df_repeated_frames_{}", std::process::id()));
+            let _ = fs::remove_dir_all(&output_dir);
+            fs::create_dir_all(&output_dir).unwrap();
+
+            let processor = FrameProcessor::new(&project_config(None, None), (4, 3)).unwrap();
+            let frame_a =
+                MonoFrame::new(vec![0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 255], 4, 3);
+            let frame_b = MonoFrame::solid_color(4, 3, 255);
+            let deduplicator = Mutex::new(FrameDeduplicator::default());
+
+            // Frame 3 repeats frame 1 within a batch, frames 4 and 5 (resampled) in a later one
+            let batches = [
+                vec![(0..1, &frame_a), (1..2, &frame_b), (2..3, &frame_a)],
+                vec![(3..5, &frame_a), (5..6, &frame_b)],
+            ];
+            for batch in &batches
             {
-                let mut mono_video = ffmpeg::util::frame::video::Video::empty();
+                write_json_frames_deduplicated(
+                    batch,
+                    (4, 3),
+                    tiling,
+                    &processor,
+                    &deduplicator,
+                    &output_dir,
+                )
+                .unwrap();
+            }
 
-                monochromatic_ctx
-                    .run(&decoded, &mut mono_video)
-                    .map_err(|e| ImplError::FFmpeg(e))?;
+            let deduplicator = deduplicator.into_inner().unwrap();
+            let mut repeats: Vec<(usize, usize)> = deduplicator.repeats.into_iter().collect();
+            repeats.sort();
+            assert_eq!(repeats, vec![(3, 1), (4, 1), (5, 1), (6, 2)]);
 
-                frames.push(MonoFrame::new(
-                    mono_video.data(0).to_vec(), // Single channel data
-                    mono_video.width() as u16,
-                    mono_video.height() as u16,
-                ));
-            }
+            let mut frame_files: Vec<String> = fs::read_dir(&output_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            frame_files.sort();
+            assert_eq!(frame_files, expected_files);
+
+            fs::remove_dir_all(&output_dir).unwrap();
         }
     }
-    // Flush decoder (could be storing extra frames)
-    decoder.send_eof().map_err(|e| ImplError::FFmpeg(e))?;
-    let mut decoded = ffmpeg::util::frame::video::Video::empty();
-    while decoder.receive_frame(&mut decoded).is_ok()
+
+    #[test]
+    fn single_frame_range()
     {
-        let mut mono_video = ffmpeg::util::frame::video::Video::empty();
-        monochromatic_ctx.run(&decoded, &mut mono_video).map_err(|e| ImplError::FFmpeg(e))?;
+        let index_range = project_index_range(&project_config(Some(5), Some(5)), None).unwrap();
+        assert_eq!(index_range, (4, Some(5)));
+
+        let layout = GridLayout::new((16, 9), None, None, None, 1);
+        let output_dir = write_grid_and_tp("single_frame_range", (4, 5), &layout);
+
+        assert_eq!(grid_frame_numbers(&output_dir), vec![Some(5)]);
+        assert_eq!(tp_target(&output_dir, 5), Some(layout.cell_center(0)));
+        assert_eq!(tp_target(&output_dir, 4), None);
+        assert_eq!(tp_target(&output_dir, 6), None);
 
-        frames.push(MonoFrame::new(
-            mono_video.data(0).to_vec(),
-            mono_video.width() as u16,
-            mono_video.height() as u16,
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn omitted_frame_end_runs_to_the_end()
+    {
+        let index_range = project_index_range(&project_config(Some(3), None), None).unwrap();
+        assert_eq!(index_range, (2, None));
+
+        let index_range = project_index_range(&project_config(None, None), None).unwrap();
+        assert_eq!(index_range, (0, None));
+    }
+
+    #[test]
+    fn inverted_range_is_rejected()
+    {
+        let error = project_index_range(&project_config(Some(5), Some(4)), None).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::InvertedFrameRange((5, 4)))
         ));
     }
-    Ok(frames)
+
+    #[test]
+    fn range_from_frame_one_includes_the_last_frame()
+    {
+        let index_range = project_index_range(&project_config(Some(1), Some(3)), None).unwrap();
+        assert_eq!(index_range, (0, Some(3)));
+
+        let layout = GridLayout::new((16, 9), None, None, None, 3);
+        let output_dir = write_grid_and_tp("range_from_frame_one", (0, 3), &layout);
+
+        assert_eq!(grid_frame_numbers(&output_dir), vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(tp_target(&output_dir, 1), Some(layout.cell_center(0)));
+        assert_eq!(tp_target(&output_dir, 3), Some(layout.cell_center(2)));
+        assert_eq!(tp_target(&output_dir, 0), None);
+        assert_eq!(tp_target(&output_dir, 4), None);
+        assert_tp_matches_grid(&output_dir, &layout);
+
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn grid_starts_at_frame_start_in_every_order()
+    {
+        for order in [GridOrder::Spiral, GridOrder::RowMajor, GridOrder::Hilbert]
+        {
+            // Frames 11 to 20
+            let layout = GridLayout::new((13, 7), Some(2), Some(order), None, 10);
+            let output_dir = write_grid_and_tp("grid_starts_at_frame_start", (10, 20), &layout);
+
+            let grid_frame_numbers = grid_frame_numbers(&output_dir);
+            let (x, z) = layout.cell_coords(0);
+            assert_eq!(grid_frame_numbers[layout::spiral_coords_to_index(x, z)], Some(11));
+
+            let mut shown: Vec<usize> = grid_frame_numbers.into_iter().flatten().collect();
+            shown.sort();
+            assert_eq!(shown, (11..=20).collect::<Vec<usize>>());
+
+            assert_eq!(tp_target(&output_dir, 11), Some(layout.cell_center(0)));
+            assert_eq!(tp_target(&output_dir, 20), Some(layout.cell_center(9)));
+            assert_tp_matches_grid(&output_dir, &layout);
+
+            fs::remove_dir_all(&output_dir).unwrap();
+        }
+    }
 }
@@ -0,0 +1,267 @@
+use std::{
+    ffi::OsString,
+    fs::{
+        self,
+        File,
+    },
+    io::Write,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde_json::json;
+use zip::{
+    CompressionMethod,
+    ZipWriter,
+    write::SimpleFileOptions,
+};
+
+use crate::{
+    ImplError,
+    PackConfig,
+    Result,
+    log::info,
+};
+
+/// Minecraft 1.21 - 1.21.1
+const DEFAULT_PACK_FORMAT: u32 = 48;
+
+/// Datapacks renamed the `functions` folder to `function` in 1.21
+const SINGULAR_FOLDERS_PACK_FORMAT: u32 = 45;
+
+const DEFAULT_DESCRIPTION: &str = "Generated by v2df";
+
+/// Folder density functions are written to, the pack's `worldgen/density_function` folder
+pub fn density_function_dir(
+    root_dir: &Path,
+    pack: Option<&PackConfig>,
+    namespace: &str,
+) -> PathBuf
+{
+    match pack
+    {
+        None => root_dir.to_owned(),
+        Some(_) => root_dir.join("data").join(namespace).join("worldgen").join("density_function"),
+    }
+}
+
+/// The pack's `worldgen/<folder>` folder, or `<folder>` when not writing a pack
+pub fn worldgen_dir(
+    root_dir: &Path,
+    pack: Option<&PackConfig>,
+    namespace: &str,
+    folder: &str,
+) -> PathBuf
+{
+    match pack
+    {
+        None => root_dir.join(folder),
+        Some(_) => root_dir.join("data").join(namespace).join("worldgen").join(folder),
+    }
+}
+
+/// The pack's `minecraft` worldgen tag folder for `registry`, or `tags/<registry>` when not
+/// writing a pack
+pub fn worldgen_tag_dir(
+    root_dir: &Path,
+    pack: Option<&PackConfig>,
+    registry: &str,
+) -> PathBuf
+{
+    match pack
+    {
+        None => root_dir.join("tags").join(registry),
+        Some(_) => root_dir.join("data/minecraft/tags/worldgen").join(registry),
+    }
+}
+
+/// Folder functions are written to, the pack's `function` (or `functions`) folder
+pub fn function_dir(
+    root_dir: &Path,
+    pack: Option<&PackConfig>,
+    namespace: &str,
+) -> PathBuf
+{
+    match pack
+    {
+        None => root_dir.to_owned(),
+        Some(pack) => root_dir.join("data").join(namespace).join(function_folder(pack)),
+    }
+}
+
+/// The pack's `minecraft` function tag folder, holding the `tick` and `load` tags
+pub fn function_tag_dir(
+    root_dir: &Path,
+    pack: &PackConfig,
+) -> PathBuf
+{
+    root_dir.join("data/minecraft/tags").join(function_folder(pack))
+}
+
+/// Adds the ID to the tag file, keeping the values already in it from other projects
+pub fn add_to_tag(
+    tag_dir: &Path,
+    tag_name: &str,
+    id: &str,
+) -> Result<()>
+{
+    fs::create_dir_all(tag_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+
+    let tag_path = tag_dir.join(format!("{}.json", tag_name));
+
+    let mut values: Vec<String> = fs::read_to_string(&tag_path)
+        .ok()
+        .and_then(|tag_string| serde_json::from_str::<serde_json::Value>(&tag_string).ok())
+        .and_then(|tag| serde_json::from_value(tag["values"].clone()).ok())
+        .unwrap_or_default();
+
+    if !values.iter().any(|value| value == id)
+    {
+        values.push(id.to_owned());
+    }
+
+    let tag_string = serde_json::to_string_pretty(&json!({ "values": values }))
+        .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))?;
+    fs::write(&tag_path, &tag_string).map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+    Ok(())
+}
+
+pub fn write_pack_mcmeta(
+    root_dir: &Path,
+    pack: &PackConfig,
+) -> Result<()>
+{
+    let pack_mcmeta = json!(
+        {
+            "pack": {
+                "pack_format": pack_format(pack),
+                "description": pack.description.as_deref().unwrap_or(DEFAULT_DESCRIPTION)
+            }
+        }
+    );
+    let pack_mcmeta_string = serde_json::to_string_pretty(&pack_mcmeta)
+        .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))?;
+    fs::write(root_dir.join("pack.mcmeta"), &pack_mcmeta_string)
+        .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+    Ok(())
+}
+
+/// Folder the pack is put together in before it gets zipped, next to `output_root_dir` so a
+/// large pack stays on the same disk as the zip instead of a temp dir that may live in memory
+pub fn staging_dir(output_root_dir: &Path) -> PathBuf
+{
+    let mut staging_dir = OsString::from(output_root_dir.as_os_str());
+    staging_dir.push(".v2df_staging");
+    PathBuf::from(staging_dir)
+}
+
+/// Creates the staging dir and removes it again when dropped, however the run ends
+pub struct StagingDir
+{
+    path: PathBuf,
+}
+
+impl StagingDir
+{
+    /// Clears out what a run that never got to clean up left behind, so none of it gets zipped
+    pub fn create(path: &Path) -> Result<StagingDir>
+    {
+        if path.exists()
+        {
+            fs::remove_dir_all(path).map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+        }
+        fs::create_dir_all(path).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+
+        Ok(StagingDir {
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl Drop for StagingDir
+{
+    fn drop(&mut self)
+    {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// `<output_root_dir>.zip`, kept next to where the folder would have been
+pub fn zip_path(output_root_dir: &Path) -> PathBuf
+{
+    let mut zip_path = OsString::from(output_root_dir.as_os_str());
+    zip_path.push(".zip");
+    PathBuf::from(zip_path)
+}
+
+/// Zips everything inside `dir`, with paths relative to it
+pub fn zip_dir(
+    dir: &Path,
+    zip_path: &Path,
+) -> Result<()>
+{
+    let zip_file = File::create(zip_path).map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+
+    let mut zip = ZipWriter::new(zip_file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    add_dir_to_zip(&mut zip, dir, dir, options)?;
+
+    zip.finish().map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
+
+    info!("Saved datapack to {}", zip_path.display());
+    Ok(())
+}
+
+fn add_dir_to_zip(
+    zip: &mut ZipWriter<File>,
+    dir: &Path,
+    root_dir: &Path,
+    options: SimpleFileOptions,
+) -> Result<()>
+{
+    let entries = fs::read_dir(dir).map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+
+    for entry in entries
+    {
+        let path = entry.map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?.path();
+
+        if path.is_dir()
+        {
+            add_dir_to_zip(zip, &path, root_dir, options)?;
+            continue;
+        }
+
+        // Zip entries always use forward slashes
+        let entry_name = path
+            .strip_prefix(root_dir)
+            .unwrap()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let contents = fs::read(&path).map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+
+        zip.start_file(entry_name, options)
+            .map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
+        zip.write_all(&contents).map_err(|e| ImplError::FileCompression(format!("{:?}", e)))?;
+    }
+    Ok(())
+}
+
+fn function_folder(pack: &PackConfig) -> &'static str
+{
+    match pack_format(pack) >= SINGULAR_FOLDERS_PACK_FORMAT
+    {
+        true => "function",
+        false => "functions",
+    }
+}
+
+fn pack_format(pack: &PackConfig) -> u32
+{
+    pack.pack_format.unwrap_or(DEFAULT_PACK_FORMAT)
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::{
+    ImplError,
+    PlaybackConfig,
+    Result,
+    log::info,
+    pack,
+};
+
+const DEFAULT_PLAYBACK_DIR: &str = "./playback";
+const DEFAULT_OBJECTIVE: &str = "v2df_playback";
+
+/// Writes the functions driving playback, with `tick` added to the `#minecraft:tick` tag
+///
+/// Scoreboard fake players hold the state: `#frame` (the frame number shown), `#tick` (ticks
+/// spent on it so far) and `#playing` (1 while playing). Frames are shown through the tp
+/// functions, numbered `index_range.0 + 1..=index_range.1`.
+pub fn write_playback_functions(
+    playback_config: &PlaybackConfig,
+    namespace: &str,
+    tp_function_prefix: &str,
+    index_range: (usize, usize),
+    function_dir: &Path,
+    function_tag_dir: &Path,
+) -> Result<()>
+{
+    let relative_dir =
+        playback_config.dir.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_PLAYBACK_DIR));
+    let relative_part = relative_dir.strip_prefix("./").unwrap_or(&relative_dir);
+    let playback_id = format!("{}:{}", namespace, relative_part.to_string_lossy());
+
+    let output_dir = function_dir.join(&relative_dir);
+    fs::create_dir_all(&output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+
+    let objective = playback_config.objective.as_deref().unwrap_or(DEFAULT_OBJECTIVE);
+    let ticks_per_frame = playback_config.ticks_per_frame.map_or(1, |ticks| ticks.get());
+    let (first_frame, last_frame) = (index_range.0 + 1, index_range.1);
+
+    let at_end = match playback_config.repeat.unwrap_or(false)
+    {
+        true => format!("scoreboard players set #frame {} {}", objective, first_frame),
+        false => format!("function {}/stop", playback_id),
+    };
+
+    let functions = [
+        (
+            "start",
+            format!(
+                "scoreboard objectives add {objective} dummy\n\
+                 scoreboard players set #frame {objective} {first_frame}\n\
+                 scoreboard players set #tick {objective} 0\n\
+                 scoreboard players set #playing {objective} 1\n\
+                 function {playback_id}/show\n"
+            ),
+        ),
+        (
+            "stop",
+            format!(
+                "scoreboard players set #playing {objective} 0\n\
+                 scoreboard players set #frame {objective} {first_frame}\n\
+                 scoreboard players set #tick {objective} 0\n"
+            ),
+        ),
+        ("pause", format!("scoreboard players set #playing {objective} 0\n")),
+        ("resume", format!("scoreboard players set #playing {objective} 1\n")),
+        // Called with a frame number, e.g. `function <namespace>:playback/seek {frame: 120}`
+        (
+            "seek",
+            format!(
+                "$scoreboard players set #frame {objective} $(frame)\n\
+                 execute if score #frame {objective} matches ..{below_first} run scoreboard \
+                 players set #frame {objective} {first_frame}\n\
+                 execute if score #frame {objective} matches {after_last}.. run scoreboard \
+                 players set #frame {objective} {last_frame}\n\
+                 scoreboard players set #tick {objective} 0\n\
+                 function {playback_id}/show\n",
+                below_first = first_frame as isize - 1,
+                after_last = last_frame + 1,
+            ),
+        ),
+        (
+            "tick",
+            format!(
+                "execute if score #playing {objective} matches 1 run function \
+                 {playback_id}/advance\n"
+            ),
+        ),
+        (
+            "advance",
+            format!(
+                "scoreboard players add #tick {objective} 1\n\
+                 execute if score #tick {objective} matches {ticks_per_frame}.. run function \
+                 {playback_id}/next_frame\n"
+            ),
+        ),
+        (
+            "next_frame",
+            format!(
+                "scoreboard players set #tick {objective} 0\n\
+                 scoreboard players add #frame {objective} 1\n\
+                 execute if score #frame {objective} matches {after_last}.. run {at_end}\n\
+                 execute if score #playing {objective} matches 1 run function {playback_id}/show\n",
+                after_last = last_frame + 1,
+            ),
+        ),
+        // Function macros can't read scores, so the frame number goes through storage
+        (
+            "show",
+            format!(
+                "execute store result storage {playback_id} frame int 1 run scoreboard players \
+                 get #frame {objective}\n\
+                 function {playback_id}/show_frame with storage {playback_id}\n"
+            ),
+        ),
+        ("show_frame", format!("$function {}$(frame)\n", tp_function_prefix)),
+    ];
+
+    for (name, function) in functions
+    {
+        fs::write(output_dir.join(format!("{}.mcfunction", name)), &function)
+            .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+    }
+
+    pack::add_to_tag(function_tag_dir, "tick", &format!("{}/tick", playback_id))?;
+
+    info!("Wrote playback functions, start with /function {}/start", playback_id);
+    Ok(())
+}
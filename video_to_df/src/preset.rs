@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    path::Path,
+};
+
+use serde_json::{
+    Value,
+    json,
+};
+
+use crate::{
+    ImplError,
+    PackConfig,
+    Result,
+    WorldPresetConfig,
+    log::info,
+    pack,
+};
+
+const DEFAULT_PRESET_NAME: &str = "video";
+const DEFAULT_BASE_HEIGHT: i32 = 0;
+/// Spreads the 0-255 gradient over 128 blocks
+const DEFAULT_HEIGHT_SCALE: f64 = 0.5;
+const DEFAULT_BLOCK: &str = "minecraft:white_concrete";
+/// No features or structures getting placed on top of the frames
+const DEFAULT_BIOME: &str = "minecraft:the_void";
+
+/// Vanilla overworld height range
+const MIN_Y: i32 = -64;
+const HEIGHT: i32 = 384;
+
+/// Writes the noise settings building terrain out of the grid DF, the world preset using them,
+/// and the `normal` world preset tag that lists it on the world creation screen
+pub fn write_world_preset(
+    preset_config: &WorldPresetConfig,
+    namespace: &str,
+    grid_id: &str,
+    root_dir: &Path,
+    pack: Option<&PackConfig>,
+) -> Result<()>
+{
+    let name = preset_config.name.as_deref().unwrap_or(DEFAULT_PRESET_NAME);
+    let preset_id = format!("{}:{}", namespace, name);
+
+    write_json(
+        &pack::worldgen_dir(root_dir, pack, namespace, "noise_settings"),
+        name,
+        &noise_settings_json(preset_config, grid_id),
+    )?;
+
+    write_json(
+        &pack::worldgen_dir(root_dir, pack, namespace, "world_preset"),
+        name,
+        &world_preset_json(preset_config, &preset_id),
+    )?;
+
+    pack::add_to_tag(
+        &pack::worldgen_tag_dir(root_dir, pack, "world_preset"),
+        "normal",
+        &preset_id,
+    )?;
+
+    info!("Wrote world preset {}", preset_id);
+    Ok(())
+}
+
+/// Terrain is solid below `base_height + height_scale * grid` and air above it
+fn noise_settings_json(
+    preset_config: &WorldPresetConfig,
+    grid_id: &str,
+) -> Value
+{
+    let base_height = preset_config.base_height.unwrap_or(DEFAULT_BASE_HEIGHT);
+    let height_scale = preset_config.height_scale.unwrap_or(DEFAULT_HEIGHT_SCALE);
+    let block = preset_config.block.as_deref().unwrap_or(DEFAULT_BLOCK);
+
+    let final_density = json!(
+        {
+            "type": "minecraft:add",
+            "argument1": {
+                "type": "minecraft:add",
+                "argument1": base_height,
+                "argument2": {
+                    "type": "minecraft:mul",
+                    "argument1": height_scale,
+                    "argument2": grid_id
+                }
+            },
+            // -y across the whole world height
+            "argument2": {
+                "type": "minecraft:y_clamped_gradient",
+                "from_y": MIN_Y,
+                "to_y": MIN_Y + HEIGHT,
+                "from_value": -MIN_Y,
+                "to_value": -(MIN_Y + HEIGHT)
+            }
+        }
+    );
+
+    json!(
+        {
+            "sea_level": MIN_Y,
+            "disable_mob_generation": true,
+            "aquifers_enabled": false,
+            "ore_veins_enabled": false,
+            "legacy_random_source": false,
+            "default_block": { "Name": block },
+            "default_fluid": { "Name": "minecraft:air" },
+            "noise": {
+                "min_y": MIN_Y,
+                "height": HEIGHT,
+                "size_horizontal": 1,
+                "size_vertical": 1
+            },
+            "noise_router": {
+                "barrier": 0,
+                "fluid_level_floodedness": 0,
+                "fluid_level_spread": 0,
+                "lava": 0,
+                "temperature": 0,
+                "vegetation": 0,
+                "continents": 0,
+                "erosion": 0,
+                "depth": 0,
+                "ridges": 0,
+                "initial_density_without_jaggedness": 0,
+                "final_density": final_density,
+                "vein_toggle": 0,
+                "vein_ridged": 0,
+                "vein_gap": 0
+            },
+            "spawn_target": [],
+            "surface_rule": { "type": "minecraft:sequence", "sequence": [] }
+        }
+    )
+}
+
+/// Overworld generated from the noise settings, the nether and end stay vanilla
+fn world_preset_json(
+    preset_config: &WorldPresetConfig,
+    preset_id: &str,
+) -> Value
+{
+    let biome = preset_config.biome.as_deref().unwrap_or(DEFAULT_BIOME);
+
+    json!(
+        {
+            "dimensions": {
+                "minecraft:overworld": {
+                    "type": "minecraft:overworld",
+                    "generator": {
+                        "type": "minecraft:noise",
+                        "settings": preset_id,
+                        "biome_source": { "type": "minecraft:fixed", "biome": biome }
+                    }
+                },
+                "minecraft:the_nether": {
+                    "type": "minecraft:the_nether",
+                    "generator": {
+                        "type": "minecraft:noise",
+                        "settings": "minecraft:nether",
+                        "biome_source": {
+                            "type": "minecraft:multi_noise",
+                            "preset": "minecraft:nether"
+                        }
+                    }
+                },
+                "minecraft:the_end": {
+                    "type": "minecraft:the_end",
+                    "generator": {
+                        "type": "minecraft:noise",
+                        "settings": "minecraft:end",
+                        "biome_source": { "type": "minecraft:the_end" }
+                    }
+                }
+            }
+        }
+    )
+}
+
+fn write_json(
+    output_dir: &Path,
+    name: &str,
+    json: &Value,
+) -> Result<()>
+{
+    fs::create_dir_all(output_dir).map_err(|e| ImplError::CreateDirectory(format!("{:?}", e)))?;
+    let json_string = serde_json::to_string_pretty(json)
+        .map_err(|e| ImplError::JsonPrettifier(format!("{:?}", e)))?;
+    fs::write(output_dir.join(format!("{}.json", name)), &json_string)
+        .map_err(|e| ImplError::FileWrite(format!("{:?}", e)))?;
+    Ok(())
+}
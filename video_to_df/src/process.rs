@@ -0,0 +1,534 @@
+use std::borrow::Cow;
+
+use image::imageops::FilterType;
+use rayon::prelude::*;
+
+use crate::{
+    CliError,
+    CropRect,
+    DistanceMetric,
+    GradientScale,
+    MonoFrame,
+    ProjectConfig,
+    Result,
+    ScaleConfig,
+    ScaleFilter,
+    SourceConfig,
+    Threshold,
+    log::info,
+    sdf,
+    source,
+};
+
+/// Rows and columns at the edges that never get brighter than this are letterbox bars
+const LETTERBOX_LEVEL: u8 = 24;
+
+/// Everything that turns a source frame into the gradated frame written for a project
+pub struct FrameProcessor
+{
+    source_dim: (u16, u16),
+    crop: Option<CropRect>,
+    scale_config: Option<ScaleConfig>,
+    scale: Option<(u16, u16, FilterType)>,
+    invert_colors: bool,
+    border_width: u16,
+    border_color: u8,
+    metric: DistanceMetric,
+    /// `None` finds the threshold for each frame on its own
+    threshold: Option<u8>,
+    max_distances: Option<(f32, f32)>,
+    /// Statistics over every frame in the range still to be collected by `collect_statistics`,
+    /// in the order they depend on each other
+    pending: Vec<Statistic>,
+}
+
+/// A statistic over every frame in a project's range, needed before any frame can be written
+#[derive(Clone, Copy, PartialEq)]
+enum Statistic
+{
+    /// Crop trimming the edges that stay black, changes what the others see
+    Letterbox,
+    /// Threshold of the combined histogram, changes the distances
+    Otsu,
+    /// Farthest distances, for the global gradient scale
+    GlobalMax,
+}
+
+/// A statistic part way through the frames
+enum Accumulator
+{
+    /// Brightest pixel of every row and every column
+    Letterbox(Vec<u8>, Vec<u8>),
+    Otsu(Box<[u64; 256]>),
+    /// Farthest distance (above, below)
+    GlobalMax(f32, f32),
+}
+
+impl Statistic
+{
+    fn description(self) -> &'static str
+    {
+        match self
+        {
+            Statistic::Letterbox => "letterbox",
+            Statistic::Otsu => "Otsu threshold",
+            Statistic::GlobalMax => "global gradient scale",
+        }
+    }
+}
+
+impl FrameProcessor
+{
+    /// Settings that need to see every frame in the range first are left pending for
+    /// `collect_statistics`
+    pub fn new(
+        project_config: &ProjectConfig,
+        source_dim: (u16, u16),
+    ) -> Result<FrameProcessor>
+    {
+        let mut processor = FrameProcessor {
+            source_dim,
+            crop: None,
+            scale_config: project_config.scale,
+            scale: None,
+            invert_colors: project_config.invert_colors.unwrap_or(false),
+            border_width: project_config.border_width,
+            border_color: project_config.border_color,
+            metric: project_config.distance_metric.unwrap_or_default(),
+            threshold: None,
+            max_distances: None,
+            pending: Vec::new(),
+        };
+
+        if let Some(crop) = project_config.crop
+        {
+            let fits_x = crop.x as u32 + crop.width as u32 <= source_dim.0 as u32;
+            let fits_y = crop.y as u32 + crop.height as u32 <= source_dim.1 as u32;
+            if crop.width == 0 || crop.height == 0 || !fits_x || !fits_y
+            {
+                return Err(CliError::InvalidCrop(
+                    (crop.x, crop.y, crop.width, crop.height),
+                    source_dim,
+                )
+                .into());
+            }
+            processor.crop = Some(crop);
+        }
+
+        if project_config.remove_letterbox.unwrap_or(false)
+        {
+            processor.pending.push(Statistic::Letterbox);
+        }
+
+        processor.update_scale()?;
+
+        processor.threshold =
+            match project_config.threshold.unwrap_or(Threshold::Fixed(sdf::DEFAULT_THRESHOLD))
+            {
+                Threshold::Fixed(threshold) => Some(threshold),
+                Threshold::Otsu =>
+                {
+                    processor.pending.push(Statistic::Otsu);
+                    None
+                },
+                Threshold::Adaptive => None,
+            };
+
+        processor.max_distances = match project_config.gradient_scale.unwrap_or_default()
+        {
+            GradientScale::PerFrame => None,
+            GradientScale::Fixed(max_distance) =>
+            {
+                Some((max_distance.get() as f32, max_distance.get() as f32))
+            },
+            GradientScale::Global =>
+            {
+                processor.pending.push(Statistic::GlobalMax);
+                None
+            },
+        };
+
+        Ok(processor)
+    }
+
+    /// Whether the letterbox is still to be found, until then `prepared_dim` is the size
+    /// before it's trimmed
+    pub fn awaits_letterbox(&self) -> bool
+    {
+        self.pending.contains(&Statistic::Letterbox)
+    }
+
+    pub fn process(
+        &self,
+        frame: &MonoFrame,
+    ) -> MonoFrame
+    {
+        self.gradate(&self.prepare(frame))
+    }
+
+    /// Size of the frames coming out of `prepare`, before the border
+    pub fn prepared_dim(&self) -> (u16, u16)
+    {
+        match self.scale
+        {
+            Some((width, height, _)) => (width, height),
+            None => self.cropped_dim(),
+        }
+    }
+
+    /// Adjustments to the source frame itself, everything before the border
+    pub fn prepare<'f>(
+        &self,
+        frame: &'f MonoFrame,
+    ) -> Cow<'f, MonoFrame>
+    {
+        let mut prepared = self.crop_frame(frame);
+
+        if let Some((width, height, filter)) = self.scale
+        {
+            prepared = Cow::Owned(prepared.resize(width, height, filter));
+        }
+
+        if self.invert_colors
+        {
+            prepared = Cow::Owned(prepared.invert());
+        }
+
+        prepared
+    }
+
+    /// Adds the border and gradient to an already prepared frame
+    pub fn gradate(
+        &self,
+        prepared: &MonoFrame,
+    ) -> MonoFrame
+    {
+        sdf::binary_sdf(
+            &prepared.add_border(self.border_width, self.border_color),
+            self.metric,
+            self.threshold_for(prepared),
+            self.max_distances,
+        )
+    }
+
+    /// The threshold an already prepared frame is split at
+    pub fn threshold_for(
+        &self,
+        prepared: &MonoFrame,
+    ) -> u8
+    {
+        self.threshold.unwrap_or_else(|| sdf::otsu_threshold(&sdf::histogram(&prepared.data)))
+    }
+
+    fn cropped_dim(&self) -> (u16, u16)
+    {
+        match self.crop
+        {
+            Some(crop) => (crop.width, crop.height),
+            None => self.source_dim,
+        }
+    }
+
+    /// The scale follows the crop, a missing side keeps its aspect ratio
+    fn update_scale(&mut self) -> Result<()>
+    {
+        if let Some(scale) = &self.scale_config
+        {
+            let (width, height) = scaled_dim(scale, self.cropped_dim())?;
+            self.scale = Some((width, height, filter_type(scale.filter.unwrap_or_default())));
+        }
+        Ok(())
+    }
+
+    fn crop_frame<'f>(
+        &self,
+        frame: &'f MonoFrame,
+    ) -> Cow<'f, MonoFrame>
+    {
+        match self.crop
+        {
+            Some(crop) => Cow::Owned(frame.crop(crop.x, crop.y, crop.width, crop.height)),
+            None => Cow::Borrowed(frame),
+        }
+    }
+
+    fn accumulator(
+        &self,
+        statistic: Statistic,
+    ) -> Accumulator
+    {
+        let (width, height) = self.cropped_dim();
+        match statistic
+        {
+            Statistic::Letterbox =>
+            {
+                Accumulator::Letterbox(vec![0; height as usize], vec![0; width as usize])
+            },
+            Statistic::Otsu => Accumulator::Otsu(Box::new([0; 256])),
+            // Starts at 1 pixel so a side that never appears can't divide by 0
+            Statistic::GlobalMax => Accumulator::GlobalMax(1.0, 1.0),
+        }
+    }
+
+    fn accumulate(
+        &self,
+        accumulator: &mut Accumulator,
+        frames: &[&MonoFrame],
+    )
+    {
+        match accumulator
+        {
+            Accumulator::Letterbox(row_max, column_max) =>
+            {
+                let (width, height) = self.cropped_dim();
+                let batch_max = frames
+                    .par_iter()
+                    .map(|frame| row_and_column_max(&self.crop_frame(frame)))
+                    .reduce(|| (vec![0; height as usize], vec![0; width as usize]), max_per_line);
+
+                let edge_max = (std::mem::take(row_max), std::mem::take(column_max));
+                (*row_max, *column_max) = max_per_line(edge_max, batch_max);
+            },
+            Accumulator::Otsu(histogram) =>
+            {
+                let batch_histogram = frames
+                    .par_iter()
+                    .map(|frame| sdf::histogram(&self.prepare(frame).data))
+                    .reduce(|| [0; 256], add_histograms);
+
+                **histogram = add_histograms(**histogram, batch_histogram);
+            },
+            Accumulator::GlobalMax(max_above, max_below) =>
+            {
+                let batch_max = frames
+                    .par_iter()
+                    .map(|frame| {
+                        let prepared = self.prepare(frame);
+                        let bordered = prepared.add_border(self.border_width, self.border_color);
+                        let (width, height) = (bordered.width as usize, bordered.height as usize);
+                        let (above, below) = sdf::sdf_distances(
+                            &bordered,
+                            self.metric,
+                            self.threshold_for(&prepared),
+                        );
+
+                        // Frames without pixels on one side would otherwise count the capped
+                        // distance
+                        (
+                            sdf::max_seeded_distance(&above, width, height).unwrap_or(0.0),
+                            sdf::max_seeded_distance(&below, width, height).unwrap_or(0.0),
+                        )
+                    })
+                    .reduce(|| (0.0, 0.0), |a, b| (a.0.max(b.0), a.1.max(b.1)));
+
+                *max_above = max_above.max(batch_max.0);
+                *max_below = max_below.max(batch_max.1);
+            },
+        }
+    }
+
+    /// Settles the setting waiting on the statistic, once every frame in the range was seen
+    fn apply(
+        &mut self,
+        accumulator: Accumulator,
+    ) -> Result<()>
+    {
+        self.pending.remove(0);
+
+        match accumulator
+        {
+            Accumulator::Letterbox(row_max, column_max) =>
+            {
+                self.crop = self.letterbox_crop(&row_max, &column_max).or(self.crop);
+                self.update_scale()?;
+            },
+            Accumulator::Otsu(histogram) =>
+            {
+                let threshold = sdf::otsu_threshold(&histogram);
+                info!("Otsu threshold: {}", threshold);
+                self.threshold = Some(threshold);
+            },
+            Accumulator::GlobalMax(max_above, max_below) =>
+            {
+                info!("Global gradient scale: {:.1}px above, {:.1}px below", max_above, max_below);
+                self.max_distances = Some((max_above, max_below));
+            },
+        }
+        Ok(())
+    }
+
+    /// Crop trimming the rows and columns at the edges that stay black in every frame,
+    /// `None` when the frames are black all the way through
+    fn letterbox_crop(
+        &self,
+        row_max: &[u8],
+        column_max: &[u8],
+    ) -> Option<CropRect>
+    {
+        let is_content = |&line_max: &u8| line_max > LETTERBOX_LEVEL;
+
+        let (Some(top), Some(bottom), Some(left), Some(right)) = (
+            row_max.iter().position(is_content),
+            row_max.iter().rposition(is_content),
+            column_max.iter().position(is_content),
+            column_max.iter().rposition(is_content),
+        )
+        else
+        {
+            info!("No letterbox found, frames are black");
+            return None;
+        };
+
+        let (offset_x, offset_y) = self.crop.map_or((0, 0), |crop| (crop.x, crop.y));
+        let letterbox_crop = CropRect {
+            x: offset_x + left as u16,
+            y: offset_y + top as u16,
+            width: (right - left + 1) as u16,
+            height: (bottom - top + 1) as u16,
+        };
+
+        info!(
+            "Letterbox crop: {}x{} at ({}, {})",
+            letterbox_crop.width, letterbox_crop.height, letterbox_crop.x, letterbox_crop.y
+        );
+
+        Some(letterbox_crop)
+    }
+}
+
+/// Collects the statistics every processor is waiting on in shared passes over the source, each
+/// processor seeing only the frames in its own range
+///
+/// A pass collects the first statistic each processor still needs, so one pass covers every
+/// project unless a project needs statistics that depend on each other
+pub fn collect_statistics(
+    source: &SourceConfig,
+    processors: &mut [(&mut FrameProcessor, (usize, Option<usize>))],
+) -> Result<()>
+{
+    loop
+    {
+        let pass: Vec<(usize, Statistic)> = processors
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (processor, _))| {
+                processor.pending.first().map(|statistic| (i, *statistic))
+            })
+            .collect();
+        if pass.is_empty()
+        {
+            return Ok(());
+        }
+
+        let mut descriptions = Vec::new();
+        for (_, statistic) in &pass
+        {
+            if !descriptions.contains(&statistic.description())
+            {
+                descriptions.push(statistic.description());
+            }
+        }
+        info!("Scanning the source for the {}...", descriptions.join(", "));
+
+        let index_ranges: Vec<(usize, Option<usize>)> =
+            pass.iter().map(|(i, _)| processors[*i].1).collect();
+        let mut frames =
+            source::open_frame_source(source, source::union_index_range(&index_ranges))?;
+
+        let mut accumulators: Vec<Accumulator> =
+            pass.iter().map(|(i, statistic)| processors[*i].0.accumulator(*statistic)).collect();
+        loop
+        {
+            let batch = frames.next_batch(source::frame_batch_size())?;
+            if batch.is_empty()
+            {
+                break;
+            }
+
+            for ((i, _), accumulator) in pass.iter().zip(&mut accumulators)
+            {
+                let (processor, index_range) = &processors[*i];
+                let frames_in_range: Vec<&MonoFrame> = batch
+                    .iter()
+                    .filter(|(index, _)| source::range_contains(*index_range, *index))
+                    .map(|(_, frame)| frame)
+                    .collect();
+                processor.accumulate(accumulator, &frames_in_range);
+            }
+        }
+
+        for ((i, _), accumulator) in pass.into_iter().zip(accumulators)
+        {
+            processors[i].0.apply(accumulator)?;
+        }
+    }
+}
+
+fn add_histograms(
+    mut a: [u64; 256],
+    b: [u64; 256],
+) -> [u64; 256]
+{
+    a.iter_mut().zip(b).for_each(|(a_count, b_count)| *a_count += b_count);
+    a
+}
+
+fn scaled_dim(
+    scale: &ScaleConfig,
+    (width, height): (u16, u16),
+) -> Result<(u16, u16)>
+{
+    let aspect_ratio = width as f64 / height as f64;
+    match (scale.width, scale.height)
+    {
+        (Some(width), Some(height)) => Ok((width.get(), height.get())),
+        (Some(width), None) =>
+        {
+            Ok((width.get(), ((width.get() as f64 / aspect_ratio).round() as u16).max(1)))
+        },
+        (None, Some(height)) =>
+        {
+            Ok((((height.get() as f64 * aspect_ratio).round() as u16).max(1), height.get()))
+        },
+        (None, None) => Err(CliError::InvalidScale.into()),
+    }
+}
+
+fn filter_type(filter: ScaleFilter) -> FilterType
+{
+    match filter
+    {
+        ScaleFilter::Nearest => FilterType::Nearest,
+        ScaleFilter::Bilinear => FilterType::Triangle,
+        ScaleFilter::CatmullRom => FilterType::CatmullRom,
+        ScaleFilter::Gaussian => FilterType::Gaussian,
+        ScaleFilter::Lanczos3 => FilterType::Lanczos3,
+    }
+}
+
+/// Brightest pixel in every row and every column of the frame
+fn row_and_column_max(frame: &MonoFrame) -> (Vec<u8>, Vec<u8>)
+{
+    let mut row_max = vec![0; frame.height as usize];
+    let mut column_max = vec![0; frame.width as usize];
+
+    for (y, row) in frame.data.chunks(frame.width as usize).enumerate()
+    {
+        for (x, &pixel_val) in row.iter().enumerate()
+        {
+            row_max[y] = row_max[y].max(pixel_val);
+            column_max[x] = column_max[x].max(pixel_val);
+        }
+    }
+    (row_max, column_max)
+}
+
+fn max_per_line(
+    mut a: (Vec<u8>, Vec<u8>),
+    b: (Vec<u8>, Vec<u8>),
+) -> (Vec<u8>, Vec<u8>)
+{
+    a.0.iter_mut().zip(b.0).for_each(|(a_max, b_max)| *a_max = (*a_max).max(b_max));
+    a.1.iter_mut().zip(b.1).for_each(|(a_max, b_max)| *a_max = (*a_max).max(b_max));
+    a
+}
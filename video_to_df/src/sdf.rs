@@ -1,30 +1,42 @@
-use crate::MonoFrame;
+use crate::{
+    DistanceMetric,
+    MonoFrame,
+};
 
-pub fn binary_sdf(frame: &MonoFrame) -> MonoFrame
+/// Pixels above this value count as above the threshold unless configured otherwise
+pub const DEFAULT_THRESHOLD: u8 = 127;
+
+/// Stand-in for "no seed pixel yet" in the squared Euclidean transform, kept finite so the
+/// parabola intersections never produce `inf - inf`
+const EDT_UNREACHED: f64 = 1e20;
+
+/// Gradates the frame, `max_distances` (above, below) fixes the distances that map to the ends
+/// of the gradient, otherwise each frame's own farthest distances are used
+pub fn binary_sdf(
+    frame: &MonoFrame,
+    metric: DistanceMetric,
+    threshold: u8,
+    max_distances: Option<(f32, f32)>,
+) -> MonoFrame
 {
-    // Compute the above threshold and below threshold SDF
-    // Splits 0-127 & 128-255;
-    let above_distances =
-        chebyshev_sdf_above(&frame.data, frame.width as usize, frame.height as usize, 127);
-    let below_distances =
-        chebyshev_sdf_below(&frame.data, frame.width as usize, frame.height as usize, 127);
+    let (above_distances, below_distances) = sdf_distances(frame, metric, threshold);
 
     // Then, find the `max_value` in them
-    let above_max = *above_distances.iter().max().expect("SDF should never have size 0");
-    let below_max = *below_distances.iter().max().expect("SDF should never have size 0");
+    let (above_max, below_max) = max_distances
+        .unwrap_or_else(|| (max_distance(&above_distances), max_distance(&below_distances)));
 
-    // Then, convert the `_bytes` from `usize` to `u8` by normalizing to `_max` and clamping
+    // Then, convert the `_bytes` from `f32` to `u8` by normalizing to `_max` and clamping
     let above_bytes: Vec<u8> = above_distances
         .iter()
         .map(|&dist| {
-            let norm = 1.0 - (dist as f32 / above_max as f32);
+            let norm = 1.0 - (dist / above_max);
             (norm * 127.0).round().clamp(0.0, 127.0) as u8
         })
         .collect();
     let below_bytes: Vec<u8> = below_distances
         .iter()
         .map(|&dist| {
-            let norm = dist as f32 / below_max as f32;
+            let norm = dist / below_max;
             128 + (norm * 127.0).round().clamp(0.0, 127.0) as u8
         })
         .collect();
@@ -46,74 +58,161 @@ pub fn binary_sdf(frame: &MonoFrame) -> MonoFrame
     MonoFrame::new(combined_bytes, frame.width, frame.height)
 }
 
-fn chebyshev_sdf_below(
-    image: &[u8],
+/// Distances from every pixel to the nearest pixel above and below the threshold
+pub fn sdf_distances(
+    frame: &MonoFrame,
+    metric: DistanceMetric,
+    threshold: u8,
+) -> (Vec<f32>, Vec<f32>)
+{
+    // Compute the above threshold and below threshold SDF
+    // Splits 0-threshold & (threshold+1)-255;
+    let above_distances =
+        sdf_above(&frame.data, frame.width as usize, frame.height as usize, threshold, metric);
+    let below_distances =
+        sdf_below(&frame.data, frame.width as usize, frame.height as usize, threshold, metric);
+
+    (above_distances, below_distances)
+}
+
+pub fn max_distance(distances: &[f32]) -> f32
+{
+    distances.iter().copied().reduce(f32::max).expect("SDF should never have size 0")
+}
+
+/// Farthest distance to a seed, `None` when there were no seeds and every distance is capped
+pub fn max_seeded_distance(
+    distances: &[f32],
     width: usize,
     height: usize,
-    threshold: u8,
-) -> Vec<usize>
+) -> Option<f32>
 {
-    // max distance for chebyshev
-    let max_dist = width + height;
+    Some(max_distance(distances)).filter(|&max_dist| max_dist < (width + height) as f32)
+}
+
+pub fn histogram(data: &[u8]) -> [u64; 256]
+{
+    let mut histogram = [0; 256];
+    for &pixel_val in data
+    {
+        histogram[pixel_val as usize] += 1;
+    }
+    histogram
+}
 
-    let mut distance_field: Vec<usize> = vec![max_dist; width * height];
+/// Otsu's method, the split maximizing the variance between the two classes (0-t & (t+1)-255)
+pub fn otsu_threshold(histogram: &[u64; 256]) -> u8
+{
+    let total: u64 = histogram.iter().sum();
+    let total_sum: f64 =
+        histogram.iter().enumerate().map(|(val, &count)| (val as u64 * count) as f64).sum();
 
-    // Sets the distance field value at that position to 0 where the pixel value is above threshold
-    distance_field.iter_mut().zip(image.iter()).for_each(|(dist_val, pixel_val)| {
-        if pixel_val <= &threshold
+    let mut threshold = DEFAULT_THRESHOLD;
+    let mut best_variance = 0.0;
+    let mut below_count = 0;
+    let mut below_sum = 0.0;
+    for (val, &count) in histogram.iter().enumerate()
+    {
+        below_count += count;
+        if below_count == 0
+        {
+            continue;
+        }
+        let above_count = total - below_count;
+        if above_count == 0
         {
-            *dist_val = 0;
+            break;
         }
-    });
+        below_sum += (val as u64 * count) as f64;
 
-    chebyshev_sdf_forward_pass(&mut distance_field, width, height);
+        let below_mean = below_sum / below_count as f64;
+        let above_mean = (total_sum - below_sum) / above_count as f64;
+        let variance = below_count as f64 * above_count as f64 * (below_mean - above_mean).powi(2);
 
-    // Better access pattern to reverse all at once and walk forward
-    distance_field.reverse();
-    chebyshev_sdf_forward_pass(&mut distance_field, width, height);
+        if variance > best_variance
+        {
+            best_variance = variance;
+            threshold = val as u8;
+        }
+    }
+    threshold
+}
 
-    // Change to normal order
-    distance_field.reverse();
+fn sdf_below(
+    image: &[u8],
+    width: usize,
+    height: usize,
+    threshold: u8,
+    metric: DistanceMetric,
+) -> Vec<f32>
+{
+    // Pixels at or below the threshold are the zero distance seeds
+    let seeds: Vec<bool> = image.iter().map(|pixel_val| pixel_val <= &threshold).collect();
 
-    distance_field
+    distance_field(&seeds, width, height, metric)
 }
 
-fn chebyshev_sdf_above(
+fn sdf_above(
     image: &[u8],
     width: usize,
     height: usize,
     threshold: u8,
-) -> Vec<usize>
+    metric: DistanceMetric,
+) -> Vec<f32>
 {
-    // max distance for chebyshev
-    let max_dist = width + height;
+    // Pixels above the threshold are the zero distance seeds
+    let seeds: Vec<bool> = image.iter().map(|pixel_val| pixel_val > &threshold).collect();
 
-    let mut distance_field: Vec<usize> = vec![max_dist; width * height];
+    distance_field(&seeds, width, height, metric)
+}
 
-    // Sets the distance field value at that position to 0 where the pixel value is above threshold
-    distance_field.iter_mut().zip(image.iter()).for_each(|(dist_val, pixel_val)| {
-        if pixel_val > &threshold
-        {
-            *dist_val = 0;
-        }
-    });
+/// Distance from every pixel to the nearest seed pixel, capped at `width + height`
+fn distance_field(
+    seeds: &[bool],
+    width: usize,
+    height: usize,
+    metric: DistanceMetric,
+) -> Vec<f32>
+{
+    match metric
+    {
+        DistanceMetric::Chebyshev => chamfer_distance_field(seeds, width, height, true),
+        DistanceMetric::Manhattan => chamfer_distance_field(seeds, width, height, false),
+        DistanceMetric::Euclidean => euclidean_distance_field(seeds, width, height),
+    }
+}
+
+fn chamfer_distance_field(
+    seeds: &[bool],
+    width: usize,
+    height: usize,
+    diagonals: bool,
+) -> Vec<f32>
+{
+    // max distance for chebyshev and manhattan
+    let max_dist = width + height;
+
+    // Sets the distance field value at that position to 0 where there is a seed
+    let mut distance_field: Vec<usize> =
+        seeds.iter().map(|&is_seed| if is_seed { 0 } else { max_dist }).collect();
 
-    chebyshev_sdf_forward_pass(&mut distance_field, width, height);
+    chamfer_forward_pass(&mut distance_field, width, height, diagonals);
 
     // Better access pattern to reverse all at once and walk forward
     distance_field.reverse();
-    chebyshev_sdf_forward_pass(&mut distance_field, width, height);
+    chamfer_forward_pass(&mut distance_field, width, height, diagonals);
 
     // Change to normal order
     distance_field.reverse();
 
-    distance_field
+    distance_field.into_iter().map(|dist| dist.min(max_dist) as f32).collect()
 }
 
-fn chebyshev_sdf_forward_pass(
-    distance_field: &mut Vec<usize>,
+fn chamfer_forward_pass(
+    distance_field: &mut [usize],
     width: usize,
     height: usize,
+    diagonals: bool,
 )
 {
     // Forward pass (right, bottom-right, bottom, bottom-left)
@@ -131,7 +230,7 @@ fn chebyshev_sdf_forward_pass(
             }
 
             // Top-right Diagonal (if within bounds)
-            if (x != (width - 1)) && (y != 0)
+            if diagonals && (x != (width - 1)) && (y != 0)
             {
                 curr_dist = curr_dist.min(distance_field[idx - width + 1] + 1);
             }
@@ -143,7 +242,7 @@ fn chebyshev_sdf_forward_pass(
             }
 
             // Top-left Diagonal (if within bounds)
-            if (x != 0) && (y != 0)
+            if diagonals && (x != 0) && (y != 0)
             {
                 curr_dist = curr_dist.min(distance_field[idx - width - 1] + 1);
             }
@@ -153,3 +252,102 @@ fn chebyshev_sdf_forward_pass(
         }
     }
 }
+
+/// Exact Euclidean distance transform (Felzenszwalb & Huttenlocher), squared distances are
+/// computed down every column and then along every row, each in linear time
+fn euclidean_distance_field(
+    seeds: &[bool],
+    width: usize,
+    height: usize,
+) -> Vec<f32>
+{
+    let max_dist = (width + height) as f32;
+
+    let mut squared_field: Vec<f64> =
+        seeds.iter().map(|&is_seed| if is_seed { 0.0 } else { EDT_UNREACHED }).collect();
+
+    // Scratch buffers shared by every column and row
+    let len = width.max(height);
+    let mut line = vec![0.0; len];
+    let mut transformed = vec![0.0; len];
+    let mut parabola_sites = vec![0; len];
+    let mut parabola_bounds = vec![0.0; len + 1];
+
+    for x in 0..width
+    {
+        for y in 0..height
+        {
+            line[y] = squared_field[y * width + x];
+        }
+        squared_edt_1d(
+            &line[..height],
+            &mut transformed[..height],
+            &mut parabola_sites,
+            &mut parabola_bounds,
+        );
+        for y in 0..height
+        {
+            squared_field[y * width + x] = transformed[y];
+        }
+    }
+
+    for y in 0..height
+    {
+        let row = &mut squared_field[y * width..(y + 1) * width];
+        line[..width].copy_from_slice(row);
+        squared_edt_1d(&line[..width], row, &mut parabola_sites, &mut parabola_bounds);
+    }
+
+    squared_field.into_iter().map(|squared| (squared.sqrt() as f32).min(max_dist)).collect()
+}
+
+/// 1D squared distance transform of `f`, the lower envelope of the parabolas rooted at each sample
+fn squared_edt_1d(
+    f: &[f64],
+    transformed: &mut [f64],
+    parabola_sites: &mut [usize],
+    parabola_bounds: &mut [f64],
+)
+{
+    let n = f.len();
+    if n == 0
+    {
+        return;
+    }
+
+    // Intersection of the parabola rooted at `q` with the one rooted at `site`
+    let intersection = |q: usize, site: usize| {
+        ((f[q] + (q * q) as f64) - (f[site] + (site * site) as f64)) / (2 * q - 2 * site) as f64
+    };
+
+    // Build the lower envelope
+    let mut k = 0;
+    parabola_sites[0] = 0;
+    parabola_bounds[0] = f64::NEG_INFINITY;
+    parabola_bounds[1] = f64::INFINITY;
+    for q in 1..n
+    {
+        let mut s = intersection(q, parabola_sites[k]);
+        while s <= parabola_bounds[k]
+        {
+            k -= 1;
+            s = intersection(q, parabola_sites[k]);
+        }
+        k += 1;
+        parabola_sites[k] = q;
+        parabola_bounds[k] = s;
+        parabola_bounds[k + 1] = f64::INFINITY;
+    }
+
+    // Sample the lower envelope
+    k = 0;
+    for (q, value) in transformed.iter_mut().enumerate()
+    {
+        while parabola_bounds[k + 1] < q as f64
+        {
+            k += 1;
+        }
+        let offset = q.abs_diff(parabola_sites[k]) as f64;
+        *value = offset * offset + f[parabola_sites[k]];
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    cmp::Ordering,
+    fs::{
+        self,
+        File,
+    },
+    io::BufReader,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use image::{
+    AnimationDecoder,
+    DynamicImage,
+    Frames,
+    ImageDecoder,
+    codecs::gif::GifDecoder,
+};
+
+use crate::{
+    CliError,
+    ImageDirConfig,
+    MonoFrame,
+    Result,
+    SortOrder,
+    SourceConfig,
+    video::VideoFrames,
+};
+
+/// Frames are read in batches of this many frames per thread
+const FRAME_BATCH_SIZE_PER_THREAD: usize = 4;
+
+/// Default `pattern` for image directories
+const DEFAULT_IMAGE_PATTERN: &str = "*.png";
+
+/// A stream of single channel frames, only yielding the frames that fall inside the
+/// `index_range` it was opened with (0-based, end exclusive, `None` = until the end)
+pub trait FrameSource
+{
+    fn width(&self) -> u16;
+
+    fn height(&self) -> u16;
+
+    /// Nominal frames per second, `None` when the source doesn't say
+    fn frame_rate(&self) -> Option<f64>
+    {
+        None
+    }
+
+    /// Number of frames in the whole source, `None` when it's only known once every frame was
+    /// decoded
+    fn frame_count(&self) -> Option<usize>
+    {
+        None
+    }
+
+    /// Number of frames read so far, including the ones skipped before the range start
+    fn frames_decoded(&self) -> usize;
+
+    /// Returns the next frame inside the range along with its 0-based index in the source
+    fn next_frame(&mut self) -> Result<Option<(usize, MonoFrame)>>;
+
+    /// Collects up to `batch_size` frames, an empty batch means the range is exhausted
+    fn next_batch(
+        &mut self,
+        batch_size: usize,
+    ) -> Result<Vec<(usize, MonoFrame)>>
+    {
+        let mut batch = Vec::with_capacity(batch_size);
+        while batch.len() < batch_size
+        {
+            match self.next_frame()?
+            {
+                Some(frame) => batch.push(frame),
+                None => break,
+            }
+        }
+        Ok(batch)
+    }
+}
+
+/// Bounded number of frames held in memory at once while streaming
+pub fn frame_batch_size() -> usize
+{
+    rayon::current_num_threads() * FRAME_BATCH_SIZE_PER_THREAD
+}
+
+/// Frame rate used to resolve timestamps and resample, `source_fps` overrides the source's own
+pub fn source_frame_rate(
+    source: &SourceConfig,
+    source_fps: Option<f64>,
+) -> Result<Option<f64>>
+{
+    match source_fps
+    {
+        Some(source_fps) => Ok(Some(source_fps)),
+        None => Ok(open_frame_source(source, (0, Some(0)))?.frame_rate()),
+    }
+}
+
+/// The source with its path resolved, so `./a.mp4`, `a.mp4` and symlinks to it compare equal,
+/// paths that can't be resolved are kept as written
+pub fn canonical_source(source: &SourceConfig) -> SourceConfig
+{
+    let canonical_path = |path: &PathBuf| fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+    match source
+    {
+        SourceConfig::VideoFile(path) => SourceConfig::VideoFile(canonical_path(path)),
+        SourceConfig::ImageDir(image_dir) => SourceConfig::ImageDir(ImageDirConfig {
+            dir: canonical_path(&image_dir.dir),
+            ..image_dir.clone()
+        }),
+        SourceConfig::ImageFile(path) => SourceConfig::ImageFile(canonical_path(path)),
+        SourceConfig::GifFile(path) => SourceConfig::GifFile(canonical_path(path)),
+    }
+}
+
+/// Smallest range covering every project's range, so only those frames get decoded
+pub fn union_index_range(index_ranges: &[(usize, Option<usize>)]) -> (usize, Option<usize>)
+{
+    let index_start = index_ranges.iter().map(|range| range.0).min().unwrap_or(0);
+    let index_end = index_ranges
+        .iter()
+        .map(|range| range.1)
+        .try_fold(0, |max_end, index_end| index_end.map(|index_end| max_end.max(index_end)));
+    (index_start, index_end)
+}
+
+pub fn range_contains(
+    index_range: (usize, Option<usize>),
+    index: usize,
+) -> bool
+{
+    index >= index_range.0 && index_range.1.is_none_or(|index_end| index < index_end)
+}
+
+pub fn open_frame_source(
+    source: &SourceConfig,
+    index_range: (usize, Option<usize>),
+) -> Result<Box<dyn FrameSource>>
+{
+    Ok(match source
+    {
+        SourceConfig::VideoFile(path) => Box::new(VideoFrames::open(path, index_range)?),
+        SourceConfig::ImageDir(image_dir) =>
+        {
+            Box::new(ImageFrames::open(list_image_dir(image_dir)?, index_range)?)
+        },
+        SourceConfig::ImageFile(path) =>
+        {
+            Box::new(ImageFrames::open(vec![path.clone()], index_range)?)
+        },
+        SourceConfig::GifFile(path) => Box::new(GifFrames::open(path, index_range)?),
+    })
+}
+
+/// Still images decoded one per frame, in the order of `paths`
+pub struct ImageFrames
+{
+    paths: Vec<PathBuf>,
+    width: u16,
+    height: u16,
+    index_range: (usize, Option<usize>),
+    next_index: usize,
+}
+
+impl ImageFrames
+{
+    pub fn open(
+        paths: Vec<PathBuf>,
+        index_range: (usize, Option<usize>),
+    ) -> Result<ImageFrames>
+    {
+        // Only the header is read, every other image has to match its size
+        let first_path = paths.first().ok_or(CliError::NoImagesFound(PathBuf::new()))?;
+        let (width, height) = image::image_dimensions(first_path)
+            .map_err(|e| CliError::ImageDecode(first_path.clone(), format!("{:?}", e)))?;
+        let (width, height) = frame_dim(first_path, (width, height))?;
+
+        Ok(ImageFrames {
+            paths,
+            width,
+            height,
+            index_range,
+            next_index: 0,
+        })
+    }
+}
+
+impl FrameSource for ImageFrames
+{
+    fn width(&self) -> u16
+    {
+        self.width
+    }
+
+    fn height(&self) -> u16
+    {
+        self.height
+    }
+
+    fn frame_count(&self) -> Option<usize>
+    {
+        Some(self.paths.len())
+    }
+
+    fn frames_decoded(&self) -> usize
+    {
+        self.next_index
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(usize, MonoFrame)>>
+    {
+        let index_end = self.index_range.1.unwrap_or(usize::MAX).min(self.paths.len());
+
+        // Images before the range are never opened
+        self.next_index = self.next_index.max(self.index_range.0.min(index_end));
+
+        if self.next_index >= index_end
+        {
+            return Ok(None);
+        }
+
+        let index = self.next_index;
+        self.next_index += 1;
+
+        let path = &self.paths[index];
+        let image = image::open(path)
+            .map_err(|e| CliError::ImageDecode(path.clone(), format!("{:?}", e)))?
+            .to_luma8();
+
+        let dim = (self.width as u32, self.height as u32);
+        if image.dimensions() != dim
+        {
+            return Err(CliError::FrameSizeMismatch(path.clone(), dim, image.dimensions()).into());
+        }
+
+        Ok(Some((index, MonoFrame::new(image.into_raw(), self.width, self.height))))
+    }
+}
+
+/// Animated GIF frames, composited onto the full canvas by the `image` crate
+pub struct GifFrames
+{
+    path: PathBuf,
+    frames: Frames<'static>,
+    width: u16,
+    height: u16,
+    index_range: (usize, Option<usize>),
+    next_index: usize,
+}
+
+impl GifFrames
+{
+    pub fn open(
+        path: &Path,
+        index_range: (usize, Option<usize>),
+    ) -> Result<GifFrames>
+    {
+        let file = File::open(path)
+            .map_err(|e| CliError::SourceRead(path.to_owned(), format!("{:?}", e)))?;
+
+        let decoder = GifDecoder::new(BufReader::new(file))
+            .map_err(|e| CliError::ImageDecode(path.to_owned(), format!("{:?}", e)))?;
+
+        let (width, height) = frame_dim(path, decoder.dimensions())?;
+
+        Ok(GifFrames {
+            path: path.to_owned(),
+            frames: decoder.into_frames(),
+            width,
+            height,
+            index_range,
+            next_index: 0,
+        })
+    }
+}
+
+impl FrameSource for GifFrames
+{
+    fn width(&self) -> u16
+    {
+        self.width
+    }
+
+    fn height(&self) -> u16
+    {
+        self.height
+    }
+
+    fn frames_decoded(&self) -> usize
+    {
+        self.next_index
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(usize, MonoFrame)>>
+    {
+        loop
+        {
+            if self.index_range.1.is_some_and(|index_end| self.next_index >= index_end)
+            {
+                return Ok(None);
+            }
+
+            let frame = match self.frames.next()
+            {
+                None => return Ok(None),
+                Some(frame) => frame
+                    .map_err(|e| CliError::ImageDecode(self.path.clone(), format!("{:?}", e)))?,
+            };
+
+            let index = self.next_index;
+            self.next_index += 1;
+
+            // Frames before the range still have to be composited, but are never converted
+            if index < self.index_range.0
+            {
+                continue;
+            }
+
+            let image = DynamicImage::ImageRgba8(frame.into_buffer()).to_luma8();
+
+            return Ok(Some((index, MonoFrame::new(image.into_raw(), self.width, self.height))));
+        }
+    }
+}
+
+/// Source frame size as the u16 sizes frames are handled in, refusing sources too large for them
+pub fn frame_dim(
+    path: &Path,
+    (width, height): (u32, u32),
+) -> std::result::Result<(u16, u16), CliError>
+{
+    match (u16::try_from(width), u16::try_from(height))
+    {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(CliError::SourceTooLarge(path.to_owned(), (width, height))),
+    }
+}
+
+/// Lists the files in the directory matching the pattern, in the configured sort order
+fn list_image_dir(image_dir: &ImageDirConfig) -> Result<Vec<PathBuf>>
+{
+    let pattern = glob::Pattern::new(image_dir.pattern.as_deref().unwrap_or(DEFAULT_IMAGE_PATTERN))
+        .map_err(|e| CliError::InvalidImagePattern(format!("{:?}", e)))?;
+
+    let entries = fs::read_dir(&image_dir.dir)
+        .map_err(|e| CliError::SourceRead(image_dir.dir.clone(), format!("{:?}", e)))?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.file_name().is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+        })
+        .collect();
+
+    if paths.is_empty()
+    {
+        return Err(CliError::NoImagesFound(image_dir.dir.clone()).into());
+    }
+
+    match image_dir.sort_order.unwrap_or_default()
+    {
+        SortOrder::Name => paths.sort(),
+        SortOrder::Natural =>
+        {
+            paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()))
+        },
+    }
+
+    Ok(paths)
+}
+
+/// Compares strings with runs of digits ordered by their numeric value
+fn natural_cmp(
+    a: &str,
+    b: &str,
+) -> Ordering
+{
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop
+    {
+        match (a_chars.peek().copied(), b_chars.peek().copied())
+        {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() =>
+            {
+                let a_digits = take_digits(&mut a_chars);
+                let b_digits = take_digits(&mut b_chars);
+
+                // Without leading zeros, a longer run of digits is always the larger number
+                let a_number = a_digits.trim_start_matches('0');
+                let b_number = b_digits.trim_start_matches('0');
+                let ordering = a_number
+                    .len()
+                    .cmp(&b_number.len())
+                    .then_with(|| a_number.cmp(b_number))
+                    .then_with(|| a_digits.len().cmp(&b_digits.len()));
+
+                if ordering != Ordering::Equal
+                {
+                    return ordering;
+                }
+            },
+            (Some(a_char), Some(b_char)) =>
+            {
+                if a_char != b_char
+                {
+                    return a_char.cmp(&b_char);
+                }
+                a_chars.next();
+                b_chars.next();
+            },
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String
+{
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit())
+    {
+        digits.push(digit);
+    }
+    digits
+}
@@ -0,0 +1,151 @@
+use std::ops::Range;
+
+use crate::{
+    CliError,
+    FramePosition,
+    Resample,
+    Result,
+};
+
+/// Keeps float error from dropping or duplicating a frame sitting exactly on a boundary
+const STEP_EPSILON: f64 = 1e-9;
+
+/// Maps a project's output frames onto the source frames they show, output frames are numbered
+/// from the start of the range, so a timeline without resampling keeps the source numbering
+pub struct Timeline
+{
+    index_start: usize,
+    /// Source frames advanced per output frame
+    step: f64,
+}
+
+impl Timeline
+{
+    pub fn new(
+        resample: Option<Resample>,
+        index_start: usize,
+        source_fps: Option<f64>,
+    ) -> Result<Timeline>
+    {
+        let step = match resample
+        {
+            None => 1.0,
+            Some(Resample::Step(step)) => step.get() as f64,
+            Some(Resample::Fps(fps)) =>
+            {
+                if fps.is_nan() || fps <= 0.0
+                {
+                    return Err(CliError::InvalidTargetFps(fps).into());
+                }
+                source_fps.ok_or(CliError::UnknownFrameRate)? / fps
+            },
+        };
+
+        Ok(Timeline { index_start, step })
+    }
+
+    /// Output frames showing the source frame, empty when the frame gets dropped
+    pub fn output_indices(
+        &self,
+        source_index: usize,
+    ) -> Range<usize>
+    {
+        let offset = source_index.saturating_sub(self.index_start);
+        (self.index_start + self.output_len(offset))
+            ..(self.index_start + self.output_len(offset + 1))
+    }
+
+    /// Output frame range showing the source frames `index_start..index_end`
+    pub fn output_range(
+        &self,
+        index_end: usize,
+    ) -> (usize, usize)
+    {
+        (
+            self.index_start,
+            self.index_start + self.output_len(index_end.saturating_sub(self.index_start)),
+        )
+    }
+
+    /// Number of output frames showing the first `source_len` source frames of the range
+    fn output_len(
+        &self,
+        source_len: usize,
+    ) -> usize
+    {
+        ((source_len as f64 / self.step) - STEP_EPSILON).ceil().max(0.0) as usize
+    }
+}
+
+/// 0-based index of the source frame shown at the position
+///
+/// Frame positions in the config are 1-based and ranges include both ends, inside v2df they
+/// become 0-based indices with an exclusive end. Frame files, tp functions and playback go back
+/// to 1-based frame numbers, `index + 1`.
+pub fn position_index(
+    position: &FramePosition,
+    source_fps: Option<f64>,
+) -> Result<usize>
+{
+    match position
+    {
+        FramePosition::Frame(frame) => Ok((frame.get() - 1) as usize),
+        FramePosition::Time(timestamp) =>
+        {
+            let seconds = parse_timestamp(timestamp)
+                .ok_or_else(|| CliError::InvalidTimestamp(timestamp.clone()))?;
+            let source_fps = source_fps.ok_or(CliError::UnknownFrameRate)?;
+            Ok((seconds * source_fps + STEP_EPSILON).floor() as usize)
+        },
+    }
+}
+
+/// Seconds from `[[hh:]mm:]ss[.fff]`, with an optional trailing `s`
+fn parse_timestamp(timestamp: &str) -> Option<f64>
+{
+    let timestamp = timestamp.trim();
+    let timestamp = timestamp.strip_suffix('s').unwrap_or(timestamp);
+
+    let mut parts = timestamp.rsplit(':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next().map_or(Some(0), |minutes| minutes.parse().ok())?;
+    let hours: u64 = parts.next().map_or(Some(0), |hours| hours.parse().ok())?;
+
+    if parts.next().is_some() || !seconds.is_finite() || seconds < 0.0
+    {
+        return None;
+    }
+
+    Some((hours * 3600 + minutes * 60) as f64 + seconds)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn timestamps_are_read_in_every_form()
+    {
+        assert_eq!(parse_timestamp("01:02:03.500"), Some(3723.5));
+        assert_eq!(parse_timestamp("00:01:23.500"), Some(83.5));
+        assert_eq!(parse_timestamp("1:23.5"), Some(83.5));
+        assert_eq!(parse_timestamp("02:05"), Some(125.0));
+        assert_eq!(parse_timestamp("83.5"), Some(83.5));
+        assert_eq!(parse_timestamp("83.5s"), Some(83.5));
+        assert_eq!(parse_timestamp(" 12 "), Some(12.0));
+        assert_eq!(parse_timestamp("0"), Some(0.0));
+    }
+
+    #[test]
+    fn malformed_timestamps_are_rejected()
+    {
+        for timestamp in [
+            "", " ", "s", ":", "::", "-5", "-0.5", "1:-5", "-1:30", "1:2:3:4", "abc", "1:xx",
+            "1.2.3", "1:30:", ":30", "inf", "NaN", "1,5",
+        ]
+        {
+            assert_eq!(parse_timestamp(timestamp), None, "'{}' was accepted", timestamp);
+        }
+    }
+}
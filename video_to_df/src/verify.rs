@@ -0,0 +1,354 @@
+use std::{
+    collections::HashSet,
+    fs,
+    sync::Arc,
+};
+
+use crate::{
+    CliError,
+    Config,
+    FramePosition,
+    ProjectConfig,
+    Result,
+    evaluate::{
+        DensityFunction,
+        DensityFunctionLoader,
+    },
+    layout::{
+        self,
+        GridLayout,
+    },
+    log::info,
+    output,
+    pack,
+    source,
+    timeline,
+};
+
+/// Checks the files a previous `run` wrote against each other, printing every problem found
+pub fn verify_projects_from_config(config: Config) -> Result<()>
+{
+    let loader = DensityFunctionLoader::new(&config.output_root_dir, config.pack.as_ref());
+    let mut problem_count = 0;
+
+    for (n, project_config) in config.selected_projects()
+    {
+        info!("Verifying project {} ({})", n + 1, project_config.label());
+
+        if !project_config.make_grid
+        {
+            info!("  Skipped, 'make_grid' is off so there's no grid listing the frames");
+            continue;
+        }
+
+        let problems = verify_project(&config, project_config, &loader);
+        for problem in &problems
+        {
+            println!("  - {}", problem);
+        }
+        if problems.is_empty()
+        {
+            info!("  No problems found");
+        }
+        problem_count += problems.len();
+    }
+
+    if problem_count > 0
+    {
+        return Err(CliError::VerificationFailed(problem_count).into());
+    }
+    Ok(())
+}
+
+/// Follows the grid DF to every frame it shows, checking each frame's files once and each
+/// cell's tp function
+fn verify_project(
+    config: &Config,
+    project_config: &ProjectConfig,
+    loader: &DensityFunctionLoader,
+) -> Vec<String>
+{
+    let mut problems = Vec::new();
+
+    let grid_id = output::grid_id(project_config);
+    let grid_df = match load(loader, &grid_id)
+    {
+        Ok(grid_df) => grid_df,
+        Err(problem) =>
+        {
+            problems.push(problem);
+            return problems;
+        },
+    };
+    let DensityFunction::Grid {
+        x_size,
+        z_size,
+        spacing,
+        grid_cell_args,
+        ..
+    } = &*grid_df
+    else
+    {
+        problems.push(format!("'{}' isn't a grid density function", grid_id));
+        return problems;
+    };
+
+    // Cell n shows frame number `index_start + n + 1`, the same as when the grid was written
+    let index_start = match grid_index_start(config, project_config)
+    {
+        Ok(index_start) => index_start,
+        Err(e) =>
+        {
+            problems.push(format!("Can't tell which frame the grid starts at: {}", e));
+            return problems;
+        },
+    };
+
+    // Tiled grids list every tile in tile sized cells, with the gaps between frames as padding
+    let tiles =
+        project_config.tiles.map(|tiles| (tiles.columns.get() as usize, tiles.rows.get() as usize));
+    let (frame_dim, spacing) = match tiles
+    {
+        None => ((*x_size, *z_size), Some(*spacing as u16)),
+        Some((columns, rows)) =>
+        {
+            if *spacing != 0
+            {
+                problems.push(format!(
+                    "'{}' has a spacing of {}, grids of tiles place the gaps as padding tiles",
+                    grid_id, spacing
+                ));
+            }
+            ((x_size * columns, z_size * rows), project_config.grid_spacing)
+        },
+    };
+
+    // Numbers fill the spiral around the cells
+    let references =
+        grid_cell_args.iter().filter(|arg| matches!(arg, DensityFunction::Reference(_))).count();
+    let cell_count = references / tiles.map_or(1, |(columns, rows)| columns * rows);
+    let layout = GridLayout::new(
+        frame_dim,
+        spacing,
+        project_config.grid_order,
+        project_config.grid_wrap,
+        cell_count,
+    );
+
+    let frame_namespace =
+        output::create_df_namespace(&project_config.namespace, &project_config.frame_dfs_dir);
+    let tp_dir = pack::function_dir(
+        &config.output_root_dir,
+        config.pack.as_ref(),
+        &project_config.namespace,
+    )
+    .join(&project_config.tp_dir);
+
+    // Frames (or tiles, when tiled) have to match the grid's cells
+    let expected_dim = (*x_size, *z_size);
+    let mut verified_frames = HashSet::new();
+
+    for cell in 0..cell_count
+    {
+        let frame_number = index_start + cell + 1;
+
+        let cell_args: Vec<Option<&str>> = layout
+            .spiral_cells(cell, tiles)
+            .into_iter()
+            .map(|(x, z)| match grid_cell_args.get(layout::spiral_coords_to_index(x, z))
+            {
+                Some(DensityFunction::Reference(id)) => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let Some(frame_id) = cell_frame_id(&cell_args, tiles)
+        else
+        {
+            let (x, z) = layout.cell_coords(cell);
+            problems.push(format!(
+                "Frame {} isn't in the grid's cell at ({}, {}), '{}' uses another 'grid_order', \
+                 'grid_wrap' or 'tiles'",
+                frame_number, x, z, grid_id
+            ));
+            continue;
+        };
+
+        // Deduplicated frames show up more than once, their files only need checking once
+        if verified_frames.insert(frame_id.clone())
+        {
+            if frame_id.strip_prefix(&frame_namespace).is_none_or(|n| n.parse::<usize>().is_err())
+            {
+                problems.push(format!("'{}' isn't a frame in '{}'", frame_id, frame_namespace));
+            }
+            else if let Some(frame_dim) = verify_frame(loader, &frame_id, &mut problems)
+                && frame_dim != expected_dim
+            {
+                problems.push(format!(
+                    "'{}' is {}x{}, the grid's cells are {}x{}",
+                    frame_id, frame_dim.0, frame_dim.1, expected_dim.0, expected_dim.1
+                ));
+            }
+        }
+
+        if project_config.make_tp
+        {
+            let tp_path = tp_dir.join(format!("{}.mcfunction", frame_number));
+            match fs::read_to_string(&tp_path)
+            {
+                Err(_) => problems.push(format!(
+                    "Frame {} has no tp function at '{}'",
+                    frame_number,
+                    tp_path.display()
+                )),
+                Ok(tp_command)
+                    if tp_command.trim()
+                        != output::tp_command(&layout, cell, project_config.tp_height) =>
+                {
+                    problems.push(format!(
+                        "'{}' doesn't teleport to frame {} in the grid",
+                        tp_path.display(),
+                        frame_number
+                    ));
+                },
+                Ok(_) => (),
+            }
+        }
+    }
+
+    info!("  Checked {} cells showing {} frames", cell_count, verified_frames.len());
+    problems
+}
+
+/// ID of the frame the cell shows, from its reference or from the references to each of its
+/// tiles, `None` when they don't all point at the same frame
+fn cell_frame_id(
+    cell_args: &[Option<&str>],
+    tiles: Option<(usize, usize)>,
+) -> Option<String>
+{
+    let Some((columns, _)) = tiles
+    else
+    {
+        return cell_args[0].map(str::to_owned);
+    };
+
+    // Tiles come in row major order, see `GridLayout::spiral_cells`
+    let frame_ids = cell_args
+        .iter()
+        .enumerate()
+        .map(|(tile, tile_id)| {
+            (*tile_id)?.strip_suffix(&format!("_{}_{}", tile % columns, tile / columns))
+        })
+        .collect::<Option<Vec<&str>>>()?;
+    frame_ids.iter().all(|frame_id| *frame_id == frame_ids[0]).then(|| frame_ids[0].to_owned())
+}
+
+/// 0-based index of the source frame in the grid's first cell
+fn grid_index_start(
+    config: &Config,
+    project_config: &ProjectConfig,
+) -> Result<usize>
+{
+    let Some(frame_start) = &project_config.frame_start
+    else
+    {
+        return Ok(0);
+    };
+
+    // Only timestamps need the frame rate, and only then the source has to be around
+    let (source, source_fps) = config.project_source(project_config);
+    let source_fps = match frame_start
+    {
+        FramePosition::Frame(_) => None,
+        FramePosition::Time(_) => source::source_frame_rate(source, source_fps)?,
+    };
+    timeline::position_index(frame_start, source_fps)
+}
+
+/// Size of the frame, or of its tiles when tiled, `None` when its files are broken
+fn verify_frame(
+    loader: &DensityFunctionLoader,
+    frame_id: &str,
+    problems: &mut Vec<String>,
+) -> Option<(usize, usize)>
+{
+    let frame_df = load(loader, frame_id).map_err(|problem| problems.push(problem)).ok()?;
+
+    if let Some(frame_dim) = image_dim(&frame_df)
+    {
+        return Some(frame_dim);
+    }
+
+    let DensityFunction::Grid {
+        x_size,
+        z_size,
+        grid_cell_args,
+        ..
+    } = &*frame_df
+    else
+    {
+        problems.push(format!("'{}' is neither a frame nor a grid of tiles", frame_id));
+        return None;
+    };
+
+    for tile_arg in grid_cell_args
+    {
+        let DensityFunction::Reference(tile_id) = tile_arg
+        else
+        {
+            continue;
+        };
+
+        let Ok(tile_df) = load(loader, tile_id).map_err(|problem| problems.push(problem))
+        else
+        {
+            continue;
+        };
+
+        match image_dim(&tile_df)
+        {
+            Some(tile_dim) if tile_dim == (*x_size, *z_size) => (),
+            Some(tile_dim) => problems.push(format!(
+                "'{}' is {}x{}, '{}' places {}x{} tiles",
+                tile_id, tile_dim.0, tile_dim.1, frame_id, x_size, z_size
+            )),
+            None => problems.push(format!("'{}' isn't a tile of '{}'", tile_id, frame_id)),
+        }
+    }
+
+    Some((*x_size, *z_size))
+}
+
+/// Size of the image inside a frame or tile DF
+fn image_dim(density_function: &DensityFunction) -> Option<(usize, usize)>
+{
+    match density_function
+    {
+        DensityFunction::Cache(argument) => image_dim(argument),
+        DensityFunction::Tessellation { x_size, z_size, .. } => Some((*x_size, *z_size)),
+        _ => None,
+    }
+}
+
+/// Loads the DF, describing what's wrong with its file when it can't be read
+fn load(
+    loader: &DensityFunctionLoader,
+    id: &str,
+) -> std::result::Result<Arc<DensityFunction>, String>
+{
+    let df_path = loader.df_path(id);
+    if !df_path.is_file()
+    {
+        return Err(format!("'{}' has no file at '{}'", id, df_path.display()));
+    }
+
+    loader.load(id).map_err(|e| match e
+    {
+        CliError::DensityFunctionRead(_, reason) => format!("'{}' can't be read: {}", id, reason),
+        CliError::UnsupportedDensityFunction(_, df_type) =>
+        {
+            format!("'{}' uses '{}', which v2df doesn't write", id, df_type)
+        },
+        e => format!("'{}' can't be read: {:?}", id, e),
+    })
+}
@@ -0,0 +1,400 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use crate::{
+    ImplError,
+    MonoFrame,
+    Result,
+    ffmpeg,
+    source::{
+        self,
+        FrameSource,
+    },
+};
+
+/// Streams single channel frames out of a video file one at a time through ffmpeg
+pub struct VideoFrames
+{
+    video_path: PathBuf,
+    input: ffmpeg::format::context::Input,
+    decoder: ffmpeg::codec::decoder::Video,
+    monochromatic_ctx: ffmpeg::software::scaling::context::Context,
+    video_stream_index: usize,
+    frame_rate: Option<f64>,
+    /// Frame count the container declares, `None` when it doesn't
+    frame_count: Option<usize>,
+    /// Seconds per timestamp tick of the video stream
+    time_base: f64,
+    /// Timestamp of the first frame in seconds
+    start_seconds: f64,
+    index_range: (usize, Option<usize>),
+    next_index: usize,
+    /// Set by a seek, the first frame after it is numbered by its timestamp since counting
+    /// would restart at the seek, the frames after it are counted from there
+    seeked: bool,
+    eof_sent: bool,
+}
+
+impl VideoFrames
+{
+    /// Seeks to the keyframe before the range start when the frame rate is known, so frames
+    /// long before the range are never decoded
+    pub fn open<P>(
+        video_path: P,
+        index_range: (usize, Option<usize>),
+    ) -> Result<VideoFrames>
+    where
+        P: AsRef<Path>,
+    {
+        let mut frames = VideoFrames::open_from_start(video_path.as_ref(), index_range)?;
+        if index_range.0 > 0
+        {
+            frames.seek_to_range_start();
+        }
+        Ok(frames)
+    }
+
+    fn open_from_start(
+        video_path: &Path,
+        index_range: (usize, Option<usize>),
+    ) -> Result<VideoFrames>
+    {
+        ffmpeg::init().map_err(ImplError::FFmpeg)?;
+
+        let input = ffmpeg::format::input(video_path).map_err(ImplError::FFmpeg)?;
+
+        let video_stream =
+            input.streams().best(ffmpeg::media::Type::Video).ok_or(ImplError::FetchVideoStream)?;
+
+        let video_stream_index = video_stream.index();
+
+        let avg_frame_rate = video_stream.avg_frame_rate();
+        let frame_rate = (avg_frame_rate.denominator() != 0).then(|| f64::from(avg_frame_rate));
+
+        let frame_count = (video_stream.frames() > 0).then(|| video_stream.frames() as usize);
+
+        let time_base = f64::from(video_stream.time_base());
+        let start_seconds = match video_stream.start_time()
+        {
+            ffmpeg::ffi::AV_NOPTS_VALUE => 0.0,
+            start_time => start_time as f64 * time_base,
+        };
+
+        let decoder = ffmpeg::codec::context::Context::from_parameters(video_stream.parameters())
+            .map_err(ImplError::FFmpeg)?
+            .decoder()
+            .video()
+            .map_err(ImplError::FFmpeg)?;
+
+        // Frame sizes are u16 everywhere past this point
+        source::frame_dim(video_path, (decoder.width(), decoder.height()))?;
+
+        // Set up context to convert to monochromatic
+        let monochromatic_ctx = ffmpeg::software::scaling::context::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            ffmpeg::format::Pixel::GRAY8, // Single channel monochromatic
+            decoder.width(),
+            decoder.height(),
+            ffmpeg::software::scaling::flag::Flags::BILINEAR,
+        )
+        .map_err(ImplError::FFmpeg)?;
+
+        Ok(VideoFrames {
+            video_path: video_path.to_owned(),
+            input,
+            decoder,
+            monochromatic_ctx,
+            video_stream_index,
+            frame_rate,
+            frame_count,
+            time_base,
+            start_seconds,
+            index_range,
+            next_index: 0,
+            seeked: false,
+            eof_sent: false,
+        })
+    }
+
+    /// Leaves the position untouched when the frame rate is unknown or the seek fails
+    fn seek_to_range_start(&mut self)
+    {
+        let Some(frame_rate) = self.frame_rate
+        else
+        {
+            return;
+        };
+
+        let seconds = self.start_seconds + self.index_range.0 as f64 / frame_rate;
+        let timestamp = (seconds * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
+
+        // Lands on the last keyframe at or before the timestamp
+        if self.input.seek(timestamp, ..timestamp).is_ok()
+        {
+            self.decoder.flush();
+            self.seeked = true;
+        }
+    }
+
+    /// Source index of the frame from its timestamp, `None` when it has none
+    fn timestamp_index(
+        &self,
+        decoded: &ffmpeg::util::frame::video::Video,
+    ) -> Option<usize>
+    {
+        let frame_rate = self.frame_rate?;
+        let seconds = decoded.timestamp()? as f64 * self.time_base - self.start_seconds;
+        Some((seconds * frame_rate).round().max(0.0) as usize)
+    }
+
+    fn convert_to_mono(
+        &mut self,
+        decoded: &ffmpeg::util::frame::video::Video,
+    ) -> Result<MonoFrame>
+    {
+        let mut mono_video = ffmpeg::util::frame::video::Video::empty();
+
+        self.monochromatic_ctx.run(decoded, &mut mono_video).map_err(ImplError::FFmpeg)?;
+
+        // Single channel data, rows are padded out to the linesize
+        Ok(MonoFrame::from_strided(
+            mono_video.data(0),
+            mono_video.stride(0),
+            mono_video.width() as u16,
+            mono_video.height() as u16,
+        ))
+    }
+}
+
+impl FrameSource for VideoFrames
+{
+    fn width(&self) -> u16
+    {
+        self.decoder.width() as u16
+    }
+
+    fn height(&self) -> u16
+    {
+        self.decoder.height() as u16
+    }
+
+    fn frame_rate(&self) -> Option<f64>
+    {
+        self.frame_rate
+    }
+
+    fn frame_count(&self) -> Option<usize>
+    {
+        self.frame_count
+    }
+
+    fn frames_decoded(&self) -> usize
+    {
+        self.next_index
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(usize, MonoFrame)>>
+    {
+        loop
+        {
+            if self.index_range.1.is_some_and(|index_end| self.next_index >= index_end)
+            {
+                return Ok(None);
+            }
+
+            let mut decoded = ffmpeg::util::frame::video::Video::empty();
+            if self.decoder.receive_frame(&mut decoded).is_ok()
+            {
+                // Only the first frame after a seek is placed by its timestamp, rounding every
+                // timestamp would skip or repeat numbers in variable frame rate video. It has to
+                // be numbered and can't be past the range start, otherwise decoding starts over
+                // from the first frame
+                if self.seeked
+                {
+                    match self
+                        .timestamp_index(&decoded)
+                        .filter(|index| *index <= self.index_range.0)
+                    {
+                        Some(index) =>
+                        {
+                            self.next_index = index;
+                            self.seeked = false;
+                        },
+                        None =>
+                        {
+                            *self =
+                                VideoFrames::open_from_start(&self.video_path, self.index_range)?;
+                            continue;
+                        },
+                    }
+                }
+
+                let index = self.next_index;
+                self.next_index += 1;
+
+                // Frames before the range still have to be decoded, but are never converted
+                if index < self.index_range.0
+                {
+                    continue;
+                }
+
+                return Ok(Some((index, self.convert_to_mono(&decoded)?)));
+            }
+
+            // Flushed decoder (could be storing extra frames) has nothing left
+            if self.eof_sent
+            {
+                return Ok(None);
+            }
+
+            match self.input.packets().next()
+            {
+                Some((stream, packet)) =>
+                {
+                    if stream.index() == self.video_stream_index
+                    {
+                        self.decoder.send_packet(&packet).map_err(ImplError::FFmpeg)?;
+                    }
+                },
+                None =>
+                {
+                    self.decoder.send_eof().map_err(ImplError::FFmpeg)?;
+                    self.eof_sent = true;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::{
+        env,
+        fs,
+    };
+
+    use super::*;
+
+    /// Writes the frames losslessly as GRAY8 raw video, one frame per 1/25 s
+    fn write_clip(
+        clip_path: &Path,
+        frames: &[MonoFrame],
+    ) -> std::result::Result<(), ffmpeg::Error>
+    {
+        ffmpeg::init()?;
+
+        let mut output = ffmpeg::format::output(clip_path)?;
+        let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::RAWVIDEO)
+            .ok_or(ffmpeg::Error::EncoderNotFound)?;
+
+        let mut encoder =
+            ffmpeg::codec::context::Context::new_with_codec(codec).encoder().video()?;
+        encoder.set_width(frames[0].width as u32);
+        encoder.set_height(frames[0].height as u32);
+        encoder.set_format(ffmpeg::format::Pixel::GRAY8);
+        encoder.set_time_base(ffmpeg::Rational::new(1, 25));
+        encoder.set_frame_rate(Some(ffmpeg::Rational::new(25, 1)));
+        let mut encoder = encoder.open()?;
+
+        output.add_stream(codec)?.set_parameters(&encoder);
+        output.write_header()?;
+        let stream_time_base = output.stream(0).ok_or(ffmpeg::Error::StreamNotFound)?.time_base();
+
+        let mut packet = ffmpeg::Packet::empty();
+        for (n, frame) in frames.iter().enumerate()
+        {
+            let mut video_frame = ffmpeg::util::frame::video::Video::new(
+                ffmpeg::format::Pixel::GRAY8,
+                frame.width as u32,
+                frame.height as u32,
+            );
+            let stride = video_frame.stride(0);
+            for (y, row) in frame.data.chunks(frame.width as usize).enumerate()
+            {
+                video_frame.data_mut(0)[y * stride..y * stride + row.len()].copy_from_slice(row);
+            }
+            video_frame.set_pts(Some(n as i64));
+
+            encoder.send_frame(&video_frame)?;
+            while encoder.receive_packet(&mut packet).is_ok()
+            {
+                packet.set_stream(0);
+                packet.rescale_ts(ffmpeg::Rational::new(1, 25), stream_time_base);
+                packet.write_interleaved(&mut output)?;
+            }
+        }
+
+        encoder.send_eof()?;
+        while encoder.receive_packet(&mut packet).is_ok()
+        {
+            packet.set_stream(0);
+            packet.rescale_ts(ffmpeg::Rational::new(1, 25), stream_time_base);
+            packet.write_interleaved(&mut output)?;
+        }
+        output.write_trailer()
+    }
+
+    /// Frames 13 bytes wide, never a whole linesize, so every decoded row is padded
+    fn odd_width_frames() -> Vec<MonoFrame>
+    {
+        let (width, height) = (13, 5);
+        (0..4)
+            .map(|n| {
+                let data = (0..width * height).map(|i| (i * 7 + n * 31) as u8).collect();
+                MonoFrame::new(data, width as u16, height as u16)
+            })
+            .collect()
+    }
+
+    fn clip_path(test_name: &str) -> PathBuf
+    {
+        env::temp_dir().join(format!("v2df_{}_{}.nut", test_name, std::process::id()))
+    }
+
+    #[test]
+    fn odd_width_frames_decode_without_row_padding()
+    {
+        let frames = odd_width_frames();
+        let clip_path = clip_path("odd_width");
+        write_clip(&clip_path, &frames).unwrap();
+
+        let mut video = VideoFrames::open(&clip_path, (0, None)).unwrap();
+        assert_eq!((video.width(), video.height()), (13, 5));
+        for (n, expected) in frames.iter().enumerate()
+        {
+            let (index, frame) = video.next_frame().unwrap().unwrap();
+            assert_eq!(index, n);
+            assert_eq!((frame.width, frame.height), (13, 5));
+            assert_eq!(frame.data, expected.data);
+        }
+        assert!(video.next_frame().unwrap().is_none());
+
+        fs::remove_file(&clip_path).unwrap();
+    }
+
+    #[test]
+    fn frames_after_a_seek_are_counted_on()
+    {
+        let frames = odd_width_frames();
+        let clip_path = clip_path("seek");
+        write_clip(&clip_path, &frames).unwrap();
+
+        // Frames 2 and 3
+        let mut video = VideoFrames::open(&clip_path, (1, Some(3))).unwrap();
+        for (expected_index, expected) in frames.iter().enumerate().take(3).skip(1)
+        {
+            let (index, frame) = video.next_frame().unwrap().unwrap();
+            assert_eq!(index, expected_index);
+            assert_eq!(frame.data, expected.data);
+        }
+        assert!(video.next_frame().unwrap().is_none());
+        assert_eq!(video.frames_decoded(), 3);
+
+        fs::remove_file(&clip_path).unwrap();
+    }
+}